@@ -0,0 +1,230 @@
+use crate::Disassembler::Disassembler;
+use crate::MCAssembler::REGISTER_FLAG;
+
+// instructions which always leave to their target
+const JUMPS: [&str; 2] = ["JMP", "BR"];
+// instructions which leave to their target or fall through
+const BRANCHES: [&str; 2] = ["BZ", "BNZ"];
+// instructions after which the program does not go on
+const EXITS: [&str; 1] = ["INT"];
+
+pub enum Successor {
+    // (address of the block, "jump", "branch" or "fall")
+    Block(u16, &'static str),
+    // a jump through a register, such as RET
+    Indirect,
+    Exit
+}
+
+pub struct Instruction {
+    pub addr: u16,
+    pub text: String
+}
+
+pub struct BasicBlock {
+    pub start: u16,
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<Successor>
+}
+
+/// Control-flow graph of the basic blocks of the code segment
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>
+}
+
+impl Cfg {
+    /// Build the graph of the instructions in `words[start..end]`, the symbols
+    /// of `decoder` add the labels as block starts
+    pub fn build(decoder: &Disassembler, words: &[u16], start: u16, end: u16) -> Cfg {
+        // (address, name, length, target)
+        let mut code = vec![];
+        let mut addr = start as usize;
+        while addr < end as usize && addr < words.len() {
+            let (name, len) = match decoder.instruction(&words[addr..]) {
+                Some((name, _, len)) => (String::from(name), len),
+                None => (String::from("?"), 1)
+            };
+            code.push((addr as u16, name, len, target(decoder, &words[addr..], addr as u16)));
+            addr += len;
+        }
+
+        // a block starts at the entry, at every label and target, and after
+        // every instruction which may leave
+        let mut leaders = vec![start];
+        leaders.extend(decoder.symbols().labels.keys().copied().filter(|a| *a >= start && *a < end));
+        for (addr, name, len, target) in code.iter() {
+            if let Some(Some(t)) = target {
+                leaders.push(*t);
+            }
+            let name = name.as_str();
+            if JUMPS.contains(&name) || BRANCHES.contains(&name) || EXITS.contains(&name) {
+                leaders.push(addr + *len as u16);
+            }
+        }
+        leaders.sort();
+        leaders.dedup();
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for (i, (addr, name, len, target)) in code.iter().enumerate() {
+            if leaders.binary_search(addr).is_ok() || blocks.is_empty() {
+                blocks.push(BasicBlock {start: *addr, instructions: vec![], successors: vec![]});
+            }
+            let block = blocks.last_mut().unwrap();
+            let text = match decoder.decode(&words[*addr as usize..], *addr) {
+                Some((text, _)) => text,
+                None => format!(".DATA 0{:X}H", words[*addr as usize])
+            };
+            block.instructions.push(Instruction {addr: *addr, text});
+
+            let next = addr + *len as u16;
+            let last = i + 1 == code.len() || leaders.binary_search(&next).is_ok();
+            if !last {
+                continue;
+            }
+            let name = name.as_str();
+            let to_target = |kind| match target {
+                Some(Some(t)) => Successor::Block(*t, kind),
+                _ => Successor::Indirect
+            };
+            if JUMPS.contains(&name) {
+                block.successors.push(to_target("jump"));
+            } else if BRANCHES.contains(&name) {
+                block.successors.push(to_target("branch"));
+                block.successors.push(Successor::Block(next, "fall"));
+            } else if EXITS.contains(&name) {
+                block.successors.push(Successor::Exit);
+            } else {
+                block.successors.push(Successor::Block(next, "fall"));
+            }
+        }
+
+        // edges to the end of the code run into the data
+        for block in blocks.iter_mut() {
+            for s in block.successors.iter_mut() {
+                if let Successor::Block(t, _) = s {
+                    if *t >= end || *t < start {
+                        *s = Successor::Exit;
+                    }
+                }
+            }
+        }
+
+        Cfg {blocks}
+    }
+
+    /// Graphviz DOT text of the graph, every instruction is shown with its
+    /// source line when `source` is given
+    pub fn to_dot(&self, decoder: &Disassembler, source: Option<&[&str]>) -> String {
+        let symbols = decoder.symbols();
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=\"monospace\"];\n");
+        let mut indirect = false;
+        let mut exit = false;
+
+        for block in self.blocks.iter() {
+            let mut label = String::new();
+            if let Some(name) = symbols.labels.get(&block.start) {
+                label += &format!("{}:\\l", escape(name));
+            }
+            for inst in block.instructions.iter() {
+                label += &format!("{:04X}: {}", inst.addr, escape(&inst.text));
+                if let Some(line_num) = symbols.lines.get(&inst.addr) {
+                    match source.and_then(|s| s.get(line_num - 1)) {
+                        Some(line) => label += &format!("    ; {}: {}", line_num, escape(line.trim())),
+                        None => label += &format!("    ; line {}", line_num)
+                    }
+                }
+                label += "\\l";
+            }
+            dot += &format!("    b{:04X} [label=\"{}\"];\n", block.start, label);
+
+            for s in block.successors.iter() {
+                match s {
+                    Successor::Block(t, kind) => dot += &format!("    b{:04X} -> b{:04X} [label=\"{}\"];\n", block.start, t, kind),
+                    Successor::Indirect => {
+                        indirect = true;
+                        dot += &format!("    b{:04X} -> indirect [style=dashed];\n", block.start);
+                    },
+                    Successor::Exit => {
+                        exit = true;
+                        dot += &format!("    b{:04X} -> exit;\n", block.start);
+                    }
+                }
+            }
+        }
+
+        if indirect {
+            dot += "    indirect [shape=ellipse, label=\"indirect jump\"];\n";
+        }
+        if exit {
+            dot += "    exit [shape=ellipse];\n";
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+// Some(Some(address)) for a jump or branch with a known target, Some(None)
+// for one through a register, None for other instructions
+fn target(decoder: &Disassembler, words: &[u16], addr: u16) -> Option<Option<u16>> {
+    let (inst, variant, args) = decoder.isa().decode(words)?;
+    if !JUMPS.contains(&inst.name.as_str()) && !BRANCHES.contains(&inst.name.as_str()) {
+        return None;
+    }
+    let format = decoder.isa().format(inst);
+    for (i, kind) in variant.kinds.iter().enumerate() {
+        let relative = format.operand(i).is_some_and(|f| f.relative);
+        match kind.as_str() {
+            "label" if relative => return Some(Some(addr.wrapping_add(format.words as u16).wrapping_add(args[i]))),
+            "label" => return Some(Some(args[i])),
+            "addr" if args[i] & REGISTER_FLAG != 0 => return Some(None),
+            "addr" => return Some(Some(args[i])),
+            _ => ()
+        }
+    }
+    Some(None)
+}
+
+fn escape(s: &str) -> String {
+    s.replace("\\", "\\\\").replace("\"", "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MCAssembler::Assembler;
+    use crate::SymbolMap::SymbolMap;
+
+    fn edges(block: &BasicBlock) -> Vec<String> {
+        block.successors.iter().map(|s| match s {
+            Successor::Block(t, kind) => format!("{:04X} {}", t, kind),
+            Successor::Indirect => String::from("indirect"),
+            Successor::Exit => String::from("exit")
+        }).collect()
+    }
+
+    #[test]
+    fn blocks_end_at_jumps_branches_and_labels() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble("LOOP:\nDEC %A1\nBNZ %A1,LOOP\nBZ %A2,DONE\nJMP [%A3]\nDONE:\nINT 0\n");
+        let words = assembler.image_words(&image);
+        let mut decoder = Disassembler::new("docs/instructions.toml");
+        decoder.set_symbols(SymbolMap::parse(&map));
+
+        // the two LOADs of the stack start, then LOOP at 6 and DONE at 0EH
+        let cfg = Cfg::build(&decoder, &words, 0, 0x10);
+        let starts = cfg.blocks.iter().map(|b| b.start).collect::<Vec<_>>();
+        assert_eq!(starts, [0x00, 0x06, 0x0A, 0x0C, 0x0E]);
+        assert_eq!(edges(&cfg.blocks[0]), ["0006 fall"]);
+        assert_eq!(edges(&cfg.blocks[1]), ["0006 branch", "000A fall"]);
+        assert_eq!(edges(&cfg.blocks[2]), ["000E branch", "000C fall"]);
+        assert_eq!(edges(&cfg.blocks[3]), ["indirect"]);
+        assert_eq!(edges(&cfg.blocks[4]), ["exit"]);
+        assert_eq!(cfg.blocks[1].instructions.len(), 2);
+
+        let dot = cfg.to_dot(&decoder, None);
+        assert!(dot.contains("b0006 [label=\"LOOP:\\l0006: DEC %A1    ; line 2\\l"));
+        assert!(dot.contains("b0006 -> b0006 [label=\"branch\"];"));
+        assert!(dot.contains("b000C -> indirect [style=dashed];"));
+    }
+}
@@ -0,0 +1,333 @@
+use std::io::{self, BufRead, Write};
+
+use crate::MCAssembler::parse_number;
+use crate::Simulator::{Simulator, MEMORY_SIZE};
+
+// continue gives up after this many steps so that an endless loop can't hang the debugger
+const CONTINUE_LIMIT: usize = 10_000_000;
+
+const HELP: &str = "\
+step [n]            execute n instructions (default 1)
+continue            run until a breakpoint, a watchpoint or the program stops
+break LABEL|ADDR    set a breakpoint
+watch NAME|ADDR     stop when the word at this address changes
+delete LABEL|ADDR   remove a breakpoint or watchpoint
+info                list breakpoints and watchpoints
+regs                show all registers
+print %REG|NAME|[ADDR]
+x ADDR [n]          show n words of memory (default 8)
+disas [n]           disassemble n instructions around PC (default 8)
+quit";
+
+pub struct Debugger {
+    simulator: Simulator,
+    // source file content, used to show the line of each instruction
+    source: Vec<String>,
+    breakpoints: Vec<u16>,
+    // (name, address, last seen value)
+    watchpoints: Vec<(String, u16, u16)>
+}
+
+impl Debugger {
+    pub fn new(simulator: Simulator, source: Option<String>) -> Debugger {
+        let source = match source {
+            // the assembler counts lines after trimming the file
+            Some(s) => s.trim().lines().map(String::from).collect::<Vec<_>>(),
+            None => vec![]
+        };
+
+        Debugger {
+            simulator,
+            source,
+            breakpoints: vec![],
+            watchpoints: vec![]
+        }
+    }
+
+    pub fn repl(&mut self) {
+        self.show_location();
+
+        let stdin = io::stdin();
+        loop {
+            print!("(macpu) ");
+            io::stdout().flush().unwrap();
+
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(e) => panic!("[ERROR] {}", e)
+            }
+            if !self.execute(line.trim()) {
+                break;
+            }
+        }
+    }
+
+    /// Execute one debugger command, returns false when the debugger should exit
+    pub fn execute(&mut self, command: &str) -> bool {
+        let command = command.split_whitespace().collect::<Vec<_>>();
+        if command.is_empty() {
+            return true;
+        }
+
+        match (command[0], &command[1..]) {
+            ("step" | "s", args) => {
+                let n = match args.first() {
+                    Some(n) => match n.parse::<usize>() {
+                        Ok(n) => n,
+                        Err(_) => {println!("[ERROR] Bad step count {}", n); return true;}
+                    },
+                    None => 1
+                };
+                self.resume(n);
+            },
+            ("continue" | "c", []) => self.resume(CONTINUE_LIMIT),
+            ("break" | "b", [target]) => match self.resolve(target) {
+                Some(addr) => {
+                    if !self.breakpoints.contains(&addr) {
+                        self.breakpoints.push(addr);
+                    }
+                    println!("Breakpoint at {}", self.describe(addr));
+                },
+                None => println!("[ERROR] Unknown label or address {}", target)
+            },
+            ("watch" | "w", [target]) => match self.resolve(target) {
+                Some(addr) => {
+                    let value = self.simulator.memory[addr as usize];
+                    self.watchpoints.push((String::from(*target), addr, value));
+                    println!("Watchpoint on {} ({:04X}), value {:04X}", target, addr, value);
+                },
+                None => println!("[ERROR] Unknown symbol or address {}", target)
+            },
+            ("delete" | "d", [target]) => match self.resolve(target) {
+                Some(addr) => {
+                    self.breakpoints.retain(|b| *b != addr);
+                    self.watchpoints.retain(|(_, w, _)| *w != addr);
+                },
+                None => println!("[ERROR] Unknown symbol or address {}", target)
+            },
+            ("info" | "i", []) => {
+                for b in self.breakpoints.iter() {
+                    println!("breakpoint {}", self.describe(*b));
+                }
+                for (name, addr, value) in self.watchpoints.iter() {
+                    println!("watchpoint {} ({:04X}) = {:04X}", name, addr, value);
+                }
+            },
+            ("regs" | "r", []) => print!("{}", self.simulator.dump_registers()),
+            ("print" | "p", [target]) => println!("{}", self.print(target)),
+            ("x", args) if !args.is_empty() && args.len() <= 2 => {
+                let addr = match self.resolve(args[0]) {
+                    Some(a) => a as usize,
+                    None => {println!("[ERROR] Unknown symbol or address {}", args[0]); return true;}
+                };
+                let n = args.get(1).and_then(|n| n.parse::<usize>().ok()).unwrap_or(8);
+                let end = (addr + n).min(MEMORY_SIZE);
+                for (i, chunk) in self.simulator.memory[addr..end].chunks(8).enumerate() {
+                    let words = chunk.iter().map(|w| format!("{:04X}", w)).collect::<Vec<_>>().join(" ");
+                    println!("{:04X}: {}", addr + i * 8, words);
+                }
+            },
+            ("disas", args) if args.len() <= 1 => {
+                let n = args.first().and_then(|n| n.parse::<usize>().ok()).unwrap_or(8);
+                for line in self.disassemble_around_pc(n) {
+                    println!("{}", line);
+                }
+            },
+            ("help" | "h", []) => println!("{}", HELP),
+            ("quit" | "q", []) => return false,
+            _ => println!("[ERROR] Unknown command, type \"help\" for a list of commands")
+        }
+
+        true
+    }
+
+    fn resume(&mut self, max_steps: usize) {
+        for i in 0..max_steps {
+            if let Some(r) = self.simulator.step() {
+                println!("{}", r);
+                break;
+            }
+
+            let mut stop = false;
+            for (name, addr, value) in self.watchpoints.iter_mut() {
+                let new_value = self.simulator.memory[*addr as usize];
+                if new_value != *value {
+                    println!("Watchpoint {}: {:04X} -> {:04X}", name, value, new_value);
+                    *value = new_value;
+                    stop = true;
+                }
+            }
+            if self.breakpoints.contains(&self.simulator.pc()) {
+                println!("Breakpoint at {}", self.describe(self.simulator.pc()));
+                stop = true;
+            }
+            if stop {
+                break;
+            } else if i + 1 == max_steps && max_steps == CONTINUE_LIMIT {
+                println!("Stopped after {} steps", CONTINUE_LIMIT);
+            }
+        }
+
+        self.show_location();
+    }
+
+    fn print(&self, target: &str) -> String {
+        if target.starts_with("%") {
+            match self.simulator.register(target.trim_start_matches("%")) {
+                Some(v) => format!("{} = {:04X} ({})", target, v, v),
+                None => format!("[ERROR] Undefined register {}", target)
+            }
+        } else if target.starts_with("[") && target.ends_with("]") {
+            match self.resolve(target.trim_start_matches("[").trim_end_matches("]")) {
+                Some(addr) => {
+                    let v = self.simulator.memory[addr as usize];
+                    format!("{} = {:04X} ({})", target, v, v)
+                },
+                None => format!("[ERROR] Unknown symbol or address {}", target)
+            }
+        } else {
+            let symbols = self.simulator.decoder().symbols();
+            match symbols.address_of(target) {
+                // data symbols show their value, labels their address
+                Some(addr) if symbols.datas.contains_key(&addr) => {
+                    let v = self.simulator.memory[addr as usize];
+                    format!("{} = {:04X} ({})", target, v, v)
+                },
+                Some(addr) => format!("{} = {:04X}", target, addr),
+                // constants of .EQU have no address and may not fit in a word
+                None => match symbols.constants.get(target).copied().or(parse_number(target)) {
+                    Some(v) => format!("{} = {:04X} ({})", target, v, v),
+                    None => format!("[ERROR] Unknown symbol {}", target)
+                }
+            }
+        }
+    }
+
+    fn disassemble_around_pc(&self, n: usize) -> Vec<String> {
+        let pc = self.simulator.pc();
+
+        // the line table tells where instructions start, so it is safe to go back a few
+        let mut starts = self.simulator.decoder().symbols().lines.keys().copied().filter(|a| *a < pc).collect::<Vec<_>>();
+        starts.sort();
+        let mut addr = match starts.len() {
+            0 => pc,
+            l => starts[l.saturating_sub((n / 2).max(1))]
+        } as usize;
+
+        let mut lines = vec![];
+        for _ in 0..n {
+            if addr >= MEMORY_SIZE {
+                break;
+            }
+            let end = (addr + self.simulator.decoder().isa().max_words()).min(MEMORY_SIZE);
+            let (text, len) = match self.simulator.decoder().decode(&self.simulator.memory[addr..end], addr as u16) {
+                Some(d) => d,
+                None => (format!(".DATA 0{:X}H", self.simulator.memory[addr]), 1)
+            };
+            let marker = if addr == pc as usize {"=>"} else {"  "};
+            lines.push(format!("{} {:04X} {:<12}{}", marker, addr, self.label_at(addr as u16), text));
+            addr += len;
+        }
+
+        lines
+    }
+
+    fn show_location(&self) {
+        let pc = self.simulator.pc();
+        let end = (pc as usize + self.simulator.decoder().isa().max_words()).min(MEMORY_SIZE);
+        let text = match self.simulator.decoder().decode(&self.simulator.memory[pc as usize..end], pc) {
+            Some((text, _)) => text,
+            None => String::from("(bad instruction)")
+        };
+        println!("{}: {}", self.describe(pc), text);
+
+        if let Some(line_num) = self.simulator.decoder().symbols().lines.get(&pc) {
+            match self.source.get(line_num - 1) {
+                Some(line) => println!("    {} | {}", line_num, line.trim()),
+                None => println!("    line {}", line_num)
+            }
+        }
+    }
+
+    fn describe(&self, addr: u16) -> String {
+        match self.simulator.decoder().symbols().describe(addr) {
+            Some(d) => format!("{:04X} <{}>", addr, d),
+            None => format!("{:04X}", addr)
+        }
+    }
+
+    fn label_at(&self, addr: u16) -> String {
+        match self.simulator.decoder().symbols().labels.get(&addr) {
+            Some(l) => format!("{}:", l),
+            None => String::new()
+        }
+    }
+
+    // a label or data name, a register holding an address, or a number
+    fn resolve(&self, target: &str) -> Option<u16> {
+        if target.starts_with("%") {
+            return self.simulator.register(target.trim_start_matches("%"));
+        }
+        match self.simulator.decoder().symbols().address_of(target) {
+            Some(addr) => Some(addr),
+            None => parse_number(target).and_then(|a| u16::try_from(a).ok())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MCAssembler::Assembler;
+    use crate::SymbolMap::SymbolMap;
+
+    fn debugger(asm: &str) -> Debugger {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble(asm);
+
+        let mut simulator = Simulator::new("docs/instructions.toml");
+        simulator.set_symbols(SymbolMap::parse(&map));
+        simulator.load_image(&image, "big", 16);
+        Debugger::new(simulator, Some(String::from(asm)))
+    }
+
+    #[test]
+    fn print_shows_registers_data_labels_and_constants() {
+        let mut debugger = debugger(".EQU N -8\n.DATA COUNT 3\nLOAD 5,%A1\nLOOP:\nJMP LOOP\n");
+        debugger.execute("step 3");
+        assert_eq!(debugger.print("%A1"), "%A1 = 0005 (5)");
+        assert_eq!(debugger.print("COUNT"), "COUNT = 0003 (3)");
+        assert_eq!(debugger.print("[1000H]"), "[1000H] = 0003 (3)");
+        assert_eq!(debugger.print("LOOP"), "LOOP = 0009");
+        assert_eq!(debugger.print("N"), "N = FFFFFFFFFFFFFFF8 (-8)");
+        assert_eq!(debugger.print("10H"), "10H = 0010 (16)");
+        assert_eq!(debugger.print("M"), "[ERROR] Unknown symbol M");
+    }
+
+    #[test]
+    fn resolve_takes_the_numbers_of_the_assembler() {
+        let debugger = debugger("NOP\n");
+        assert_eq!(debugger.resolve("1000H"), Some(0x1000));
+        assert_eq!(debugger.resolve("17O"), Some(0o17));
+        assert_eq!(debugger.resolve("101B"), Some(0b101));
+        assert_eq!(debugger.resolve("10000H"), None);
+        assert_eq!(debugger.resolve("-1"), None);
+    }
+
+    #[test]
+    fn disassembly_starts_before_the_pc() {
+        let mut debugger = debugger("NOP\nNOP\nNOP\nNOP\n");
+        debugger.execute("step 3");
+        // the two LOADs of the stack start take six words
+        assert_eq!(debugger.simulator.pc(), 7);
+        assert_eq!(debugger.disassemble_around_pc(0), Vec::<String>::new());
+        assert_eq!(debugger.disassemble_around_pc(1), ["   0006             NOP"]);
+        assert_eq!(debugger.disassemble_around_pc(3), ["   0006             NOP", "=> 0007             NOP", "   0008             NOP"]);
+        // the LOADs of the stack start have no line, so it can't go back further
+        assert_eq!(debugger.disassemble_around_pc(20).len(), 20);
+        assert_eq!(debugger.disassemble_around_pc(20)[0], "   0006             NOP");
+    }
+}
@@ -0,0 +1,175 @@
+use crate::Isa::Isa;
+use crate::MCAssembler::{image_words, register_name};
+use crate::SymbolMap::SymbolMap;
+
+// the name of an instruction, the kind and value of each argument and its length
+pub type Instruction<'a> = (&'a str, Vec<(&'a str, u16)>, usize);
+
+pub struct Disassembler {
+    isa: Isa,
+    symbols: SymbolMap
+}
+
+impl Disassembler {
+    pub fn new(instructions_file_path: &str) -> Disassembler {
+        Disassembler {
+            isa: Isa::load(instructions_file_path),
+            symbols: SymbolMap::new()
+        }
+    }
+
+    pub fn load_map(&mut self, map_file_path: &str) {
+        self.symbols = SymbolMap::load(map_file_path);
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolMap) {
+        self.symbols = symbols;
+    }
+
+    pub fn symbols(&self) -> &SymbolMap {
+        &self.symbols
+    }
+
+    /// Decode an image of `word_size`-bit words in `endian` byte order
+    pub fn disassemble(&self, image: &[u8], endian: &str, word_size: u32) -> Vec<String> {
+        let words = image_words(image, endian, word_size);
+
+        let mut lines = vec![];
+        let mut addr = 0;
+        while addr < words.len() {
+            let in_data = match self.symbols.data_start_address {
+                Some(d) => addr >= d as usize,
+                None => false
+            };
+
+            // long runs of zeros are only padding between segments
+            let zeros = words[addr..].iter().take_while(|w| **w == 0).count();
+            if zeros >= 4 && !(addr..addr + zeros).any(|a| self.symbols.labels.contains_key(&(a as u16)) || self.symbols.datas.contains_key(&(a as u16))) {
+                lines.push(format!("    ; {:04X} - {:04X}: {} zero words", addr, addr + zeros - 1, zeros));
+                addr += zeros;
+                continue;
+            }
+
+            if let Some(label) = self.symbols.labels.get(&(addr as u16)) {
+                lines.push(format!("{}:", label));
+            }
+
+            let decoded = if in_data {
+                None
+            } else {
+                self.decode(&words[addr..], addr as u16)
+            };
+
+            match decoded {
+                Some((text, len)) => {
+                    let codes = words[addr..addr + len].iter().map(|w| format!("{:04X}", w)).collect::<Vec<_>>().join(" ");
+                    lines.push(format!("    {:04X}: {:<20}{}", addr, codes, text));
+                    addr += len;
+                },
+                None => {
+                    // unknown words are printed as data
+                    let text = match self.symbols.datas.get(&(addr as u16)) {
+                        Some(name) => format!(".DATA {} 0{:X}H", name, words[addr]),
+                        None => format!(".DATA 0{:X}H", words[addr])
+                    };
+                    lines.push(format!("    {:04X}: {:<20}{}", addr, format!("{:04X}", words[addr]), text));
+                    addr += 1;
+                }
+            }
+        }
+
+        lines
+    }
+
+    /// Look up the instruction starting at `words[0]`, returning its name,
+    /// the kind and value of each argument and its length in words
+    pub fn instruction(&self, words: &[u16]) -> Option<Instruction<'_>> {
+        let (inst, variant, args) = self.isa.decode(words)?;
+        let args = variant.kinds.iter().map(|k| k.as_str()).zip(args).collect::<Vec<_>>();
+        Some((inst.name.as_str(), args, self.isa.format(inst).words))
+    }
+
+    pub fn isa(&self) -> &Isa {
+        &self.isa
+    }
+
+    /// Text of the instruction at `addr` starting at `words[0]` and its length
+    pub fn decode(&self, words: &[u16], addr: u16) -> Option<(String, usize)> {
+        let (name, args, len) = self.instruction(words)?;
+        let format = self.isa.format(self.isa.instruction(name)?);
+
+        let mut args_str = vec![];
+        for (i, (kind, arg)) in args.into_iter().enumerate() {
+            let field = format.operand(i);
+            // relative labels are shown as the address they point to
+            let arg = match field {
+                Some(f) if f.relative && kind == "label" => addr.wrapping_add(len as u16).wrapping_add(arg),
+                _ => arg
+            };
+            let signed = field.is_some_and(|f| f.signed);
+            args_str.push(self.arg_to_string(kind, arg, signed)?);
+        }
+
+        if args_str.is_empty() {
+            Some((String::from(name), len))
+        } else {
+            Some((format!("{} {}", name, args_str.join(",")), len))
+        }
+    }
+
+    fn arg_to_string(&self, kind: &str, arg: u16, signed: bool) -> Option<String> {
+        match kind {
+            "regs" => register_name(arg).map(|r| format!("%{}", r)),
+            "addr" => {
+                if let Some(r) = register_name(arg) {
+                    Some(format!("[%{}]", r))
+                } else if let Some(name) = self.symbols.datas.get(&arg) {
+                    Some(format!("${}", name))
+                } else if let Some(label) = self.symbols.labels.get(&arg) {
+                    Some(String::from(label))
+                } else {
+                    Some(format!("[0{:X}H]", arg))
+                }
+            },
+            "imdn" if signed && (arg as i16) < 0 => Some(format!("-0{:X}H", (arg as i16).unsigned_abs())),
+            "imdn" => Some(format!("0{:X}H", arg)),
+            "label" => match self.symbols.labels.get(&arg) {
+                Some(label) => Some(String::from(label)),
+                None => Some(format!("0{:X}H", arg))
+            },
+            _ => None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MCAssembler::Assembler;
+
+    fn assemble(asm: &str) -> (Vec<u8>, String) {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble(asm);
+        (image, map)
+    }
+
+    #[test]
+    fn disassembly_assembles_to_the_same_image() {
+        let source = ["LOAD 05H,%A1", "START:", "MOV [%A2],%B3", "MOV %A3,$RESULT", "ADD %A1,%A2", "JMP START", "JMP [%D4]", "INT 03H"];
+        let (image, map) = assemble(&format!(".DATA RESULT 7\n{}\n", source.join("\n")));
+
+        let mut disassembler = Disassembler::new("docs/instructions.toml");
+        disassembler.set_symbols(SymbolMap::parse(&map));
+        // the text after the address and the words, up to the padding before the data
+        let lines = disassembler.disassemble(&image, "big", 16);
+        let text = lines.iter().take_while(|l| !l.ends_with("zero words")).map(|l| l.get(30..).unwrap_or(l).trim()).collect::<Vec<_>>();
+        // the assembler starts with the LOADs of the stack start
+        assert_eq!(text[..2], ["LOAD 02000H,%ASS", "LOAD 02000H,%ASP"]);
+        assert_eq!(text[2..], source);
+        assert!(lines.contains(&format!("    1000: {:<20}.DATA RESULT 07H", "0007")));
+
+        let (again, _) = assemble(&format!(".DATA RESULT 7\n{}\n", text[2..].join("\n")));
+        assert_eq!(again, image);
+    }
+}
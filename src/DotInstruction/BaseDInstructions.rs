@@ -1,8 +1,10 @@
 use std::collections::HashMap;
-use std::num::ParseIntError;
+use std::num::{ParseFloatError, ParseIntError};
 use std::error::Error;
 use std::fmt::{Display, Formatter};
 
+use crate::MCAssembler::{fixed_point, split_number};
+
 
 #[derive(Debug)]
 struct UnparseableStringError {
@@ -34,57 +36,141 @@ enum UnExceptedErrors {
     USE(UnparseableStringError),
     VOOERE(ValueOutOfExpressionRangeError),
     PIE(ParseIntError),
+    PFE(ParseFloatError),
     JS(String)
 }
 
-// Settings table
-pub const SETTINGS: HashMap<String, Setting_item> = HashMap::from_iter(vec![
-    (String::from("S_CODE_SA"), Setting_item::I(0x0)),
-    (String::from("S_DATA_SA"), Setting_item::I(0x2000)),
-    (String::from("S_STACK_SA"), Setting_item::I(0x3000))
-]);
+// Bytes of one value of a .VAR or .ARR data type, in the byte order of ENDIAN
+//
+// Integers are written as split_number takes them, such as -hex10 or -10H, "float"
+// and "double" take decimal literals such as "-3.25" or "1e-3", and so do the
+// Q formats such as "q8.8", which are rounded to their fixed-point value
+fn toBytes(settings: &HashMap<String, Setting_item>, inst: &str, data_type: &str, value: &str, line_num: usize) -> Result<Vec<u8>, UnExceptedErrors> {
+    let mut bytes = toLeBytes(inst, data_type, value, line_num)?;
+    match settings.get("ENDIAN") {
+        Some(Setting_item::S(e)) if e == "big" => bytes.reverse(),
+        _ => ()
+    }
+    Ok(bytes)
+}
+
+fn toLeBytes(inst: &str, data_type: &str, value: &str, line_num: usize) -> Result<Vec<u8>, UnExceptedErrors> {
+    let out_of_range = || UnExceptedErrors::VOOERE(ValueOutOfExpressionRangeError { value: String::from(value), v_type: String::from(data_type) });
+
+    if data_type.strip_prefix("q").is_some_and(|q| q.starts_with(|c: char| c.is_ascii_digit())) {
+        return match fixed_point(data_type, value) {
+            Ok((raw, bits, notes)) => {
+                for note in notes {
+                    println!("[WARNING] Line: {} - {}", line_num, note);
+                }
+                Ok(raw.to_le_bytes()[..bits as usize / 8].to_vec())
+            },
+            Err(e) => Err(UnExceptedErrors::JS(e))
+        };
+    }
+
+    match data_type {
+        "float" => {
+            let v = value.parse::<f64>().map_err(UnExceptedErrors::PFE)?;
+            if !v.is_finite() || v.abs() > f32::MAX as f64 {
+                return Err(out_of_range());
+            }
+            return Ok((v as f32).to_le_bytes().to_vec());
+        },
+        "double" => {
+            let v = value.parse::<f64>().map_err(UnExceptedErrors::PFE)?;
+            if !v.is_finite() {
+                return Err(out_of_range());
+            }
+            return Ok(v.to_le_bytes().to_vec());
+        },
+        _ => ()
+    }
+
+    let (min_value, max_value, size): (i128, i128, usize) = match data_type {
+        "byte" => (0, u8::MAX as i128, 1),
+        "sbyte" => (i8::MIN as i128, i8::MAX as i128, 1),
+        "word" => (0, u16::MAX as i128, 2),
+        "sword" => (i16::MIN as i128, i16::MAX as i128, 2),
+        "dword" | "" => (0, u32::MAX as i128, 4),
+        "sdword" => (i32::MIN as i128, i32::MAX as i128, 4),
+        "qword" => (0, u64::MAX as i128, 8),
+        _ => return Err(UnExceptedErrors::USE(UnparseableStringError { inst: String::from(inst), value: String::from(data_type) }))
+    };
+
+    // not a number, from_str_radix gives the error
+    let (negative, radix, src_str) = split_number(value).unwrap_or((false, 10, value));
+
+    let v = u64::from_str_radix(src_str, radix).map_err(UnExceptedErrors::PIE)? as i128;
+    let v = if negative {-v} else {v};
+    if v < min_value || v > max_value {
+        return Err(out_of_range());
+    }
+    // two's complement, cut to the size of the type
+    Ok(v.to_le_bytes()[..size].to_vec())
+}
+
+/// Settings table with the default value of every setting item, each
+/// DotInstrctionsProcessor keeps its own copy which .SET changes
+pub fn default_settings() -> HashMap<String, Setting_item> {
+    HashMap::from_iter(vec![
+        (String::from("S_CODE_SA"), Setting_item::I(0x0)),
+        (String::from("S_DATA_SA"), Setting_item::I(0x2000)),
+        (String::from("S_STACK_SA"), Setting_item::I(0x3000)),
+        // byte order and width of the words of the image, the same as the assembler's
+        (String::from("ENDIAN"), Setting_item::S(String::from("big"))),
+        (String::from("WORD_SIZE"), Setting_item::I(16))
+    ])
+}
+
+// the values a setting item of the kind S can take
+const SETTING_CHOICES: [(&str, &[&str]); 1] = [
+    ("ENDIAN", &["little", "big"])
+];
 
 pub enum Setting_item {
-    B(bool),
-    I(u32)
+    I(u32),
+    S(String)
 }
 
 // Requires no spaces before and after all values
 
 pub struct SET {
     setting_item: String,
-    value: String,
-    no_value_setting: bool
+    value: String
 }
 
 impl SET {
-    pub fn new(si: String, v: String, nvs: bool) -> SET {
+    pub fn new(si: String, v: String) -> SET {
         SET {
             setting_item: si,
-            value: v,
-            no_value_setting: nvs
+            value: v
         }
     }
 
-    pub fn setTable(&self, line_num: usize) -> Result<(), String> {
+    pub fn setTable(&self, settings: &mut HashMap<String, Setting_item>, line_num: usize) -> Result<(), String> {
         let mut error_infos = String::new();
         let mut error = false;
 
-        match SETTINGS.get(&self.setting_item) {
+        match settings.get(&self.setting_item) {
             None => error_infos += &format!("Line: {} - An illegal setting item {} is used in instruction {}\n", line_num, self.setting_item,String::from("SET")),
             Some(v) => match v {
-                Setting_item::B(b) => {
-                    match self.Bool() {
-                        Ok(v) => {SETTINGS.insert(self.setting_item, Setting_item::B(v)); ()},
+                Setting_item::I(_) => {
+                    match self.Int() {
+                        Ok(v) if self.setting_item == "WORD_SIZE" && v != 16 && v != 32 => {
+                            error = true;
+                            error_infos += &format!("Line: {} - {}\n", line_num, ValueOutOfExpressionRangeError { value: self.value.clone(), v_type: String::from("WORD_SIZE (16 or 32)") });
+                        },
+                        Ok(v) => {settings.insert(self.setting_item.clone(), Setting_item::I(v));},
                         Err(e) => {
                             error = true;
                             error_infos += &format!("Line: {} - {}\n", line_num, e);
                         }
                     }
                 },
-                Setting_item::I(i) => {
-                    match self.Int() {
-                        Ok(v) => {SETTINGS.insert(self.setting_item, Setting_item::I(v)); ()},
+                Setting_item::S(_) => {
+                    match self.Choice() {
+                        Ok(v) => {settings.insert(self.setting_item.clone(), Setting_item::S(v));},
                         Err(e) => {
                             error = true;
                             error_infos += &format!("Line: {} - {}\n", line_num, e);
@@ -95,31 +181,24 @@ impl SET {
         }
 
         if error {
-            return Err(error_infos);
+            Err(error_infos)
         } else {
-            return Ok(());
+            Ok(())
         }
     }
 
-    fn Bool(&self) -> Result<bool, UnparseableStringError>{
-        match self.value.as_str() {
-            "false" => Ok(false),
-            "true" => Ok(true),
-            "FALSE" => Ok(false),
-            "TRUE" => Ok(true),
-            _ => return Err(UnparseableStringError{inst: String::from("SET"), value: self.value})
+    fn Choice(&self) -> Result<String, UnparseableStringError> {
+        match SETTING_CHOICES.iter().find(|(item, _)| *item == self.setting_item) {
+            Some((_, c)) if c.contains(&self.value.as_str()) => Ok(self.value.clone()),
+            _ => Err(UnparseableStringError{inst: String::from("SET"), value: self.value.clone()})
         }
     }
 
     fn Int(&self) -> Result<u32, ParseIntError> {
-        if self.value.starts_with("hex") {
-            return u32::from_str_radix(self.value.trim_start_matches("hex"), 16);
-        } else if self.value.starts_with("oct") {
-            return u32::from_str_radix(self.value.trim_start_matches("oct"), 8);
-        } else if self.value.starts_with("bin") {
-            return u32::from_str_radix(self.value.trim_start_matches("bin"), 2);
-        } else {
-            return u32::from_str_radix(self.value.as_str(), 10);
+        match split_number(&self.value) {
+            Some((false, radix, digits)) => u32::from_str_radix(digits, radix),
+            // negative or not a number, from_str_radix gives the error
+            _ => self.value.parse::<u32>()
         }
     }
 }
@@ -139,17 +218,18 @@ impl VAR {
         }
     }
 
-    pub fn generateData(&self, line_num: usize) -> Result<Vec<u8>, String> {
+    pub fn generateData(&self, settings: &HashMap<String, Setting_item>, line_num: usize) -> Result<Vec<u8>, String> {
         let mut error_infos = String::new();
         let mut error = false;
 
-        let r = match self.calcData() {
+        let r = match self.calcData(settings, line_num) {
             Ok(v) => v,
             Err(e) => {
                 error = true;
                 match e {
                     UnExceptedErrors::JS(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                     UnExceptedErrors::PIE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
+                    UnExceptedErrors::PFE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                     UnExceptedErrors::USE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                     UnExceptedErrors::VOOERE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev)
                 }
@@ -158,73 +238,134 @@ impl VAR {
         };
 
         if error {
-            return Err(error_infos);
+            Err(error_infos)
         } else {
-            return Ok(r)
+            Ok(r)
         }
     }
 
-    fn calcData(&self) -> Result<Vec<u8>, UnExceptedErrors> {
-        let mut radix = 10;
-        let mut src_str = "";
-        if self.value.starts_with("hex") {
-            radix = 16;
-            src_str = self.value.trim_start_matches("hex");
-        } else if self.value.starts_with("oct") {
-            radix = 8;
-            src_str = self.value.trim_start_matches("oct");
-        } else if self.value.starts_with("bin") {
-            radix = 2;
-            src_str = self.value.trim_start_matches("bin");
-        } else {
-            radix = 10;
-            src_str = self.value.as_str();
-        }
-
-        let data_type = self.data_type.as_str();
-
-        let max_value = match data_type {
-            "byte" => u8::MAX as u32,
-            "word" => u16::MAX as u32,
-            "dword" | "" => u32::MAX as u32,
-            _ => return Err(UnExceptedErrors::USE(UnparseableStringError { inst: String::from("VAR"), value: self.data_type }))
-        };
-
-        match u32::from_str_radix(src_str, radix) {
-            Ok(v) => {
-                if v > max_value {
-                    return Err(UnExceptedErrors::VOOERE(ValueOutOfExpressionRangeError { value: self.value, v_type:  self.data_type}));
-                } else {
-                    match data_type {
-                        "byte" => return Ok(vec![v as u8]),
-                        // little-endian
-                        "word" => return Ok(vec![(v & 0xFF) as u8, ((v & 0xFF00) >> 8) as u8]),
-                        "dword" | "" => return Ok(vec![(v & 0xFF) as u8,  ((v & 0xFF00) >> 8) as u8, ((v & 0xFF_0000) >> 16) as u8, ((v & 0xFF00_0000) >> 24) as u8])
-                    }
-                }
-            }
-            Err(e) => return Err(UnExceptedErrors::PIE(e))
-        }
+    fn calcData(&self, settings: &HashMap<String, Setting_item>, line_num: usize) -> Result<Vec<u8>, UnExceptedErrors> {
+        toBytes(settings, "VAR", self.data_type.as_str(), self.value.as_str(), line_num)
     }
 }
 
+// types of .VAR and .ARR besides the Q formats "qM.N"
+pub const DATA_TYPES: [&str; 9] = ["byte", "sbyte", "word", "sword", "dword", "sdword", "qword", "float", "double"];
+
+// encodings of .STR and .PSTR, utf16le is little-endian whatever ENDIAN is
+pub const STR_ENCODINGS: [&str; 3] = ["ascii", "utf8", "utf16le"];
+
 pub struct STR {
     pub name: String,
-    value: String
+    encoding: String,
+    // with out """, the escapes are still in it
+    value: String,
+    // .PSTR, a word with the length comes first instead of a "\0" at the end
+    prefixed: bool
 }
 
 impl STR {
-    pub fn new(name: String, value: String) -> STR {
+    pub fn new(name: String, encoding: String, value: String, prefixed: bool) -> STR {
         STR {
             name,
-            // with out """
-            value
+            encoding,
+            value,
+            prefixed
         }
     }
 
-    pub fn generateData(&self, line_num: usize) -> Vec<u8> {
-        return self.value.into_bytes();
+    pub fn generateData(&self, settings: &HashMap<String, Setting_item>, line_num: usize) -> Result<Vec<u8>, String> {
+        let inst = if self.prefixed {"PSTR"} else {"STR"};
+        let (mut r, units, unit_size) = match self.encode() {
+            Ok(r) => r,
+            Err(e) => return Err(format!("Line: {} - In instruction {}, {}\n", line_num, inst, e))
+        };
+
+        if self.prefixed {
+            let mut length = match toBytes(settings, inst, "word", &units.to_string(), line_num) {
+                Ok(l) => l,
+                Err(_) => return Err(format!("Line: {} - In instruction {}, the string has {} units, a length prefix holds at most {}\n", line_num, inst, units, u16::MAX))
+            };
+            length.append(&mut r);
+            return Ok(length);
+        }
+        r.append(&mut vec![0; unit_size]);
+        Ok(r)
+    }
+
+    /// Number of characters of the text, without the "\0" or the length,
+    /// whatever the encoding stores them in
+    pub fn length(&self, line_num: usize) -> Result<usize, String> {
+        let inst = if self.prefixed {"PSTR"} else {"STR"};
+        match self.encode() {
+            Ok(_) => Ok(unescape(&self.value)?.chars().count()),
+            Err(e) => Err(format!("Line: {} - In instruction {}, {}\n", line_num, inst, e))
+        }
     }
+
+    // (bytes, number of code units, bytes of a code unit) of the text, the
+    // code units are bytes, or 16-bit units for utf16le
+    fn encode(&self) -> Result<(Vec<u8>, usize, usize), String> {
+        let text = unescape(&self.value)?;
+        match self.encoding.as_str() {
+            "ascii" => {
+                if let Some(c) = text.chars().find(|c| !c.is_ascii()) {
+                    return Err(format!("{:?} is not an ASCII character, use utf8 or utf16le", c));
+                }
+                Ok((text.clone().into_bytes(), text.len(), 1))
+            },
+            "utf16le" => {
+                let units = text.encode_utf16().collect::<Vec<_>>();
+                Ok((units.iter().flat_map(|u| u.to_le_bytes()).collect::<Vec<_>>(), units.len(), 2))
+            },
+            _ => Ok((text.clone().into_bytes(), text.len(), 1))
+        }
+    }
+}
+
+// The text of a string with "\n", "\t", "\\", "\"", "\0", "\xNN" (up to
+// \x7F) and "\u{...}" replaced by their characters
+fn unescape(value: &str) -> Result<String, String> {
+    let mut r = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            r.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => r.push('\n'),
+            Some('t') => r.push('\t'),
+            Some('\\') => r.push('\\'),
+            Some('\"') => r.push('\"'),
+            Some('0') => r.push('\0'),
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(v) if hex.len() == 2 && v <= 0x7F => r.push(v as char),
+                    Ok(_) if hex.len() == 2 => return Err(format!("\\x{} is not ASCII, write \\u{{{}}} for this character", hex, hex)),
+                    _ => return Err(format!("\\x needs two hex digits, got \\x{}", hex))
+                }
+            },
+            Some('u') => {
+                let rest = chars.as_str();
+                let hex = match rest.strip_prefix("{").and_then(|h| h.split_once("}")) {
+                    Some((h, _)) if !h.is_empty() && h.len() <= 6 => h,
+                    _ => return Err(String::from("\\u needs 1 to 6 hex digits in braces, such as \\u{E9}"))
+                };
+                match u32::from_str_radix(hex, 16).ok().and_then(char::from_u32) {
+                    Some(c) => r.push(c),
+                    None => return Err(format!("\\u{{{}}} is not a valid character", hex))
+                }
+                // skip "{hex}"
+                let skip = hex.len() + 2;
+                chars.by_ref().take(skip).for_each(drop);
+            },
+            Some(e) => return Err(format!("unknown escape \\{}", e)),
+            None => return Err(String::from("the string ends with a lone \\"))
+        }
+    }
+    Ok(r)
 }
 
 pub struct ARR {
@@ -242,20 +383,21 @@ impl ARR {
         }
     }
 
-    pub fn generateData(&self, line_num: usize) -> Result<Vec<u8>, String> {
+    pub fn generateData(&self, settings: &HashMap<String, Setting_item>, line_num: usize) -> Result<Vec<u8>, String> {
         let mut error = false;
         let mut error_infos = String::new();
 
         let mut r: Vec<u8> = vec![];
 
         for i in self.value.split(",") {
-            match self.toInt(i.trim()) {
-                Ok(v) => r.append(&mut v),
+            match self.toInt(settings, i.trim(), line_num) {
+                Ok(mut v) => r.append(&mut v),
                 Err(e) => {
                     error = true;
                     match e {
                         UnExceptedErrors::JS(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                         UnExceptedErrors::PIE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
+                        UnExceptedErrors::PFE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                         UnExceptedErrors::USE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev),
                         UnExceptedErrors::VOOERE(ev) => error_infos += &format!("Line: {} - {}\n", line_num, ev)
                     }
@@ -264,57 +406,239 @@ impl ARR {
         }
 
         if error {
-            return Err(error_infos);
+            Err(error_infos)
         } else {
-            return Ok(r);
+            Ok(r)
         }
     }
 
-    fn toInt(&self, v: &str) -> Result<Vec<u8>, UnExceptedErrors> {
-        let mut radix = 10;
-        let mut src_str = "";
-        if self.value.starts_with("hex") {
-            radix = 16;
-            src_str = self.value.trim_start_matches("hex");
-        } else if self.value.starts_with("oct") {
-            radix = 8;
-            src_str = self.value.trim_start_matches("oct");
-        } else if self.value.starts_with("bin") {
-            radix = 2;
-            src_str = self.value.trim_start_matches("bin");
-        } else {
-            radix = 10;
-            src_str = self.value.as_str();
+    /// Number of elements of the array
+    pub fn length(&self) -> usize {
+        self.value.split(",").count()
+    }
+
+    fn toInt(&self, settings: &HashMap<String, Setting_item>, v: &str, line_num: usize) -> Result<Vec<u8>, UnExceptedErrors> {
+        toBytes(settings, "ARR", self.data_type.as_str(), v, line_num)
+    }
+}
+
+pub struct DEF {
+    pub name: String,
+    pub value: String
+}
+pub struct FIELD {
+    pub name: String,
+    pub data_type: String,
+    // number of elements, 1 when the field is not an array
+    pub count: usize,
+    pub offset: usize,
+    // bytes of one element
+    pub size: usize
+}
+
+// The fields follow each other without padding, a field is "type name" or
+// "type name[count]" and the type may be a struct defined before
+pub struct STRUCT {
+    pub name: String,
+    pub fields: Vec<FIELD>,
+    pub size: usize
+}
+
+impl STRUCT {
+    pub fn new(name: String, lines: &[(usize, String)], structs: &HashMap<String, STRUCT>) -> Result<STRUCT, String> {
+        let mut error_infos = String::new();
+        let mut fields: Vec<FIELD> = vec![];
+        let mut offset = 0;
+
+        for (line_num, line) in lines.iter() {
+            let items = line.split_whitespace().collect::<Vec<_>>();
+            if items.len() != 2 {
+                error_infos += &format!("Line: {} - A field should look like \"word id\" or \"byte data[8]\"\n", line_num);
+                continue;
+            }
+            let data_type = items[0];
+            let (field, count) = match items[1].split_once("[") {
+                Some((f, c)) => match c.strip_suffix("]").and_then(|c| c.parse::<usize>().ok()) {
+                    Some(c) if c > 0 => (f, c),
+                    _ => {
+                        error_infos += &format!("Line: {} - The length of array field {} should be a positive number\n", line_num, f);
+                        continue;
+                    }
+                },
+                None => (items[1], 1)
+            };
+            // NAME.size is the size of the struct
+            if field.is_empty() || field.starts_with(|c: char| c.is_ascii_digit()) || field == "size" {
+                error_infos += &format!("Line: {} - {} can't be the name of a field\n", line_num, field);
+                continue;
+            }
+            if fields.iter().any(|f| f.name == field) {
+                error_infos += &format!("Line: {} - Field {} is defined more than once in {}\n", line_num, field, name);
+                continue;
+            }
+
+            let size = match structs.get(data_type) {
+                Some(s) => s.size,
+                None => match toLeBytes("STRUCT", data_type, "0", *line_num) {
+                    Ok(b) => b.len(),
+                    Err(e) => {
+                        error_infos += &format!("Line: {} - {}\n", line_num, describe(e));
+                        continue;
+                    }
+                }
+            };
+            fields.push(FIELD {
+                name: String::from(field),
+                data_type: String::from(data_type),
+                count,
+                offset,
+                size
+            });
+            offset += size * count;
         }
 
-        let data_type = self.data_type.as_str();
+        if error_infos.is_empty() {
+            Ok(STRUCT {name, fields, size: offset})
+        } else {
+            Err(error_infos)
+        }
+    }
 
-        let max_value = match data_type {
-            "byte" => u8::MAX as u32,
-            "word" => u16::MAX as u32,
-            "dword" | "" => u32::MAX as u32,
-            _ => return Err(UnExceptedErrors::USE(UnparseableStringError { inst: String::from("VAR"), value: self.data_type }))
+    /// Offset of a field in bytes, "hdr.flags" for a field of a nested struct
+    pub fn offset(&self, path: &str, structs: &HashMap<String, STRUCT>) -> Option<usize> {
+        let (name, rest) = match path.split_once(".") {
+            Some((n, r)) => (n, Some(r)),
+            None => (path, None)
         };
+        let field = self.fields.iter().find(|f| f.name == name)?;
+        match rest {
+            None => Some(field.offset),
+            Some(r) => Some(field.offset + structs.get(&field.data_type)?.offset(r, structs)?)
+        }
+    }
 
-        match u32::from_str_radix(src_str, radix) {
-            Ok(v) => {
-                if v > max_value {
-                    return Err(UnExceptedErrors::VOOERE(ValueOutOfExpressionRangeError { value: self.value, v_type:  self.data_type}));
-                } else {
-                    match data_type {
-                        "byte" => return Ok(vec![v as u8]),
-                        // little-endian
-                        "word" => return Ok(vec![(v & 0xFF) as u8, ((v & 0xFF00) >> 8) as u8]),
-                        "dword" | "" => return Ok(vec![(v & 0xFF) as u8,  ((v & 0xFF00) >> 8) as u8, ((v & 0xFF_0000) >> 16) as u8, ((v & 0xFF00_0000) >> 24) as u8])
-                    }
+    /// Bytes of an instance initialised with "id=1, data=[1,2], hdr={flags=3}",
+    /// the fields which aren't given are 0
+    pub fn generateData(&self, settings: &HashMap<String, Setting_item>, init: &str, structs: &HashMap<String, STRUCT>, line_num: usize) -> Result<Vec<u8>, String> {
+        let mut r = vec![0u8; self.size];
+        for item in splitTopLevel(init) {
+            let (name, value) = match item.split_once("=") {
+                Some((n, v)) => (n.trim(), v.trim()),
+                None => return Err(format!("Line: {} - \"{}\" should look like \"field=value\"\n", line_num, item))
+            };
+            let field = match self.fields.iter().find(|f| f.name == name) {
+                Some(f) => f,
+                None => return Err(format!("Line: {} - {} has no field {}\n", line_num, self.name, name))
+            };
+
+            let values = if field.count > 1 {
+                match value.strip_prefix("[").and_then(|v| v.strip_suffix("]")) {
+                    Some(v) => splitTopLevel(v),
+                    None => return Err(format!("Line: {} - Field {} is an array, its value should look like [1,2,3]\n", line_num, name))
                 }
+            } else {
+                vec![value]
+            };
+            if values.len() > field.count {
+                return Err(format!("Line: {} - Field {} has {} elements but {} values are given\n", line_num, name, field.count, values.len()));
+            }
+
+            for (i, v) in values.iter().enumerate() {
+                let bytes = match structs.get(&field.data_type) {
+                    Some(s) => match v.strip_prefix("{").and_then(|v| v.strip_suffix("}")) {
+                        Some(v) => s.generateData(settings, v, structs, line_num)?,
+                        None => return Err(format!("Line: {} - Field {} is a {}, its value should look like {{field=value}}\n", line_num, name, s.name))
+                    },
+                    None => match toBytes(settings, "VAR", &field.data_type, v, line_num) {
+                        Ok(b) => b,
+                        Err(e) => return Err(format!("Line: {} - {}\n", line_num, describe(e)))
+                    }
+                };
+                let start = field.offset + i * field.size;
+                r[start..start + field.size].copy_from_slice(&bytes);
             }
-            Err(e) => return Err(UnExceptedErrors::PIE(e))
         }
+        Ok(r)
     }
 }
 
-pub struct DEF {
+// ".VAR TYPE name {field=value, ...}" of a struct TYPE
+pub struct INSTANCE {
     pub name: String,
-    pub value: String
-}
\ No newline at end of file
+    pub struct_name: String,
+    // with out "{}"
+    value: String
+}
+
+impl INSTANCE {
+    pub fn new(name: String, struct_name: String, value: String) -> INSTANCE {
+        INSTANCE {
+            name,
+            struct_name,
+            value
+        }
+    }
+
+    pub fn generateData(&self, settings: &HashMap<String, Setting_item>, structs: &HashMap<String, STRUCT>, line_num: usize) -> Result<Vec<u8>, String> {
+        match structs.get(&self.struct_name) {
+            Some(s) => s.generateData(settings, &self.value, structs, line_num),
+            None => Err(format!("Line: {} - {} is not a struct defined with .STRUCT\n", line_num, self.struct_name))
+        }
+    }
+}
+
+// split at the commas which are not in [] or {}
+fn splitTopLevel(s: &str) -> Vec<&str> {
+    let mut r = vec![];
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '[' | '{' => depth += 1,
+            ']' | '}' => depth -= 1,
+            ',' if depth == 0 => {
+                r.push(s[start..i].trim());
+                start = i + 1;
+            },
+            _ => ()
+        }
+    }
+    if !s[start..].trim().is_empty() {
+        r.push(s[start..].trim());
+    }
+    r
+}
+
+fn describe(e: UnExceptedErrors) -> String {
+    match e {
+        UnExceptedErrors::JS(ev) => ev,
+        UnExceptedErrors::PIE(ev) => ev.to_string(),
+        UnExceptedErrors::PFE(ev) => ev.to_string(),
+        UnExceptedErrors::USE(ev) => ev.to_string(),
+        UnExceptedErrors::VOOERE(ev) => ev.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescape_replaces_escapes() {
+        assert_eq!(unescape(r#"a\n\t\\\"\0"#), Ok(String::from("a\n\t\\\"\0")));
+        assert_eq!(unescape(r"\x41\u{E9}\u{1F600}!"), Ok(String::from("A\u{e9}\u{1f600}!")));
+    }
+
+    #[test]
+    fn unescape_reports_bad_escapes() {
+        assert_eq!(unescape(r"\q"), Err(String::from("unknown escape \\q")));
+        assert_eq!(unescape("a\\"), Err(String::from("the string ends with a lone \\")));
+        assert!(unescape(r"\x80").unwrap_err().contains("is not ASCII"));
+        assert!(unescape(r"\x4").unwrap_err().contains("needs two hex digits"));
+        assert!(unescape(r"\xZZ").unwrap_err().contains("needs two hex digits"));
+        assert!(unescape(r"\u41").unwrap_err().contains("in braces"));
+        assert!(unescape(r"\u{}").unwrap_err().contains("in braces"));
+        assert!(unescape(r"\u{110000}").unwrap_err().contains("is not a valid character"));
+        assert!(unescape(r"\u{D800}").unwrap_err().contains("is not a valid character"));
+    }
+}
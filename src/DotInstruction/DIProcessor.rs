@@ -1,11 +1,15 @@
 use std::collections::HashMap;
-use tokio;
 use super::BaseDInstructions::{
+    default_settings,
+    Setting_item,
+    STR_ENCODINGS,
     SET,
     VAR,
     STR,
     ARR,
-    DEF
+    DEF,
+    STRUCT,
+    INSTANCE
 };
 
 enum DI {
@@ -13,13 +17,22 @@ enum DI {
     VA(VAR),
     ST(STR),
     AR(ARR),
-    DE(DEF)
+    DE(DEF),
+    IN(INSTANCE)
 }
 
+// the line number, the name and the field lines of a .STRUCT being read
+type StructBlock = (usize, String, Vec<(usize, String)>);
+
 pub struct DotInstrctionsProcessor {
     file: Vec<(usize, String)>,
     define_table: HashMap<String, String>,
     datas_table: HashMap<String, usize>,
+    // name -> (number of elements, number of bytes) of every .ARR and .STR
+    sizes_table: HashMap<String, (usize, usize)>,
+    structs_table: HashMap<String, STRUCT>,
+    // setting item -> value, changed by .SET
+    settings: HashMap<String, Setting_item>,
     datas: Vec<u8>
 }
 
@@ -29,67 +42,177 @@ impl DotInstrctionsProcessor {
             file,
             define_table: HashMap::new(),
             datas_table: HashMap::new(),
+            sizes_table: HashMap::new(),
+            structs_table: HashMap::new(),
+            settings: default_settings(),
             datas: vec![]
         }
     }
 
-    pub fn extract(&mut self) -> Vec<(usize, String)>{
-        let mut pi = vec![];
-        let mut i = vec![];
-
-        for (line_num, line) in self.file {
-            if line.starts_with(".") {
-                pi.push((line_num, line));
-            } else {
-                i.push((line_num, line));
+    /// Value of a size symbol of an .ARR or .STR: "NAME.len" and
+    /// "LENGTHOF(NAME)" are its number of elements, "NAME.size" and
+    /// "SIZEOF(NAME)" its number of bytes. For a .STRUCT "NAME.field" is the
+    /// offset of the field and "NAME.size" or "SIZEOF(NAME)" its size
+    pub fn symbol(&self, name: &str) -> Option<usize> {
+        let struct_name = name.strip_prefix("SIZEOF(").and_then(|n| n.strip_suffix(")")).map(|n| n.trim());
+        if let Some(s) = struct_name.and_then(|n| self.structs_table.get(n)) {
+            return Some(s.size);
+        }
+        if let Some((n, path)) = name.split_once(".") {
+            if let Some(s) = self.structs_table.get(n) {
+                return if path == "size" {Some(s.size)} else {s.offset(path, &self.structs_table)};
             }
         }
 
-        self.file = pi;
-        return i;
+        let (data, length) = if let Some(n) = name.strip_suffix(".len") {
+            (n, true)
+        } else if let Some(n) = name.strip_suffix(".size") {
+            (n, false)
+        } else if let Some(n) = name.strip_prefix("LENGTHOF(").and_then(|n| n.strip_suffix(")")) {
+            (n.trim(), true)
+        } else if let Some(n) = name.strip_prefix("SIZEOF(").and_then(|n| n.strip_suffix(")")) {
+            (n.trim(), false)
+        } else {
+            return None;
+        };
+
+        self.sizes_table.get(data).map(|(len, size)| if length {*len} else {*size})
+    }
+
+    /// Whether `name` is a piece of data, a struct or a define
+    pub fn defines(&self, name: &str) -> bool {
+        self.datas_table.contains_key(name) || self.structs_table.contains_key(name) || self.define_table.contains_key(name)
+    }
+
+    /// Set a setting item as .SET does, such as the ENDIAN of the data
+    pub fn set(&mut self, setting_item: &str, value: &str) -> Result<(), String> {
+        SET::new(String::from(setting_item), String::from(value)).setTable(&mut self.settings, 0)
+    }
+
+    /// Value of a number setting item, such as S_STACK_SA
+    pub fn setting(&self, setting_item: &str) -> Option<u32> {
+        match self.settings.get(setting_item) {
+            Some(Setting_item::I(v)) => Some(*v),
+            _ => None
+        }
+    }
+
+    /// Name and bytes of every piece of data, in the order of the data
+    pub fn items(&self) -> Vec<(String, Vec<u8>)> {
+        let mut offsets = self.datas_table.iter().collect::<Vec<_>>();
+        offsets.sort_by_key(|(_, offset)| **offset);
+        offsets.iter().enumerate().map(|(i, (name, offset))| {
+            let end = offsets.get(i + 1).map_or(self.datas.len(), |(_, next)| **next);
+            ((*name).clone(), self.datas[**offset..end].to_vec())
+        }).collect()
     }
 
-    pub async fn process(&mut self) -> Result<(), String> {
-        let mut dip_handles = vec![];
+    pub fn process(&mut self) -> Result<(), String> {
         let mut errors = String::new();
 
+        // structs are built in order before the other lines, a struct may use
+        // the structs before it and .VAR needs all structs
+        let mut file = vec![];
+        let mut block: Option<StructBlock> = None;
         for (line_num, line) in self.file.clone() {
-            let dip = DIProcessor::new(line_num, line);
-            // oh no
-            dip_handles.push(tokio::spawn(dip.start()))
+            if line.starts_with(".STRUCT") {
+                if let Some((l, _, _)) = block {
+                    errors += &format!("Line: {} - The .STRUCT on line {} has no .ENDS\n", line_num, l);
+                }
+                let name = line.trim_start_matches(".STRUCT").trim();
+                if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) || name.contains(" ") {
+                    errors += &format!("Line: {} - \"{}\" can't be the name of a struct\n", line_num, name);
+                }
+                block = Some((line_num, String::from(name), vec![]));
+            } else if line.starts_with(".ENDS") {
+                match block.take() {
+                    Some((l, name, fields)) => {
+                        if self.structs_table.contains_key(&name) {
+                            errors += &format!("Line: {} - \"{}\" has already been defined\n", l, name);
+                            continue;
+                        }
+                        match STRUCT::new(name.clone(), &fields, &self.structs_table) {
+                            Ok(s) => {self.structs_table.insert(name, s);},
+                            Err(e) => errors += &e
+                        }
+                    },
+                    None => errors += &format!("Line: {} - .ENDS without a .STRUCT\n", line_num)
+                }
+            } else if let Some((_, _, fields)) = block.as_mut() {
+                fields.push((line_num, line));
+            } else {
+                file.push((line_num, line));
+            }
+        }
+        if let Some((l, _, _)) = block {
+            errors += &format!("Line: {} - The .STRUCT has no .ENDS\n", l);
         }
 
-        for h in dip_handles {
-            match h.await.unwrap() {
+        // the data is placed in the order of the lines
+        for (line_num, line) in file {
+            match DIProcessor::new(line_num, line).start() {
                 Ok((l, v)) => match v {
-                    // Here, due to asynchronous operations causing data to be out of order,
-                    // a hashmap is needed to record the position of each data
                     DI::AR(d) => {
+                        if self.datas_table.contains_key(&d.name) {
+                            errors += &format!("Line: {} - \"{}\" has already been defined\n", l, d.name);
+                            continue;
+                        }
                         self.datas_table.insert(d.name.clone(), self.datas.len());
-                        match d.generateData(l) {
-                            Ok(u) => self.datas.append(&mut u.clone()),
+                        match d.generateData(&self.settings, l) {
+                            Ok(u) => {
+                                self.sizes_table.insert(d.name.clone(), (d.length(), u.len()));
+                                self.datas.append(&mut u.clone())
+                            },
                             Err(e) => errors += &e
                         }
                     },
                     DI::DE(d) => {
-                        match self.define_table.get(&d.name) {
-                            Some(_) => errors += &format!("Line: {} - \"{}\" has already been defined\n", l, d.name),
-                            None => {self.define_table.insert(d.name, d.value);}
-                        };
+                        match self.define_table.entry(d.name) {
+                            std::collections::hash_map::Entry::Occupied(e) => errors += &format!("Line: {} - \"{}\" has already been defined\n", l, e.key()),
+                            std::collections::hash_map::Entry::Vacant(e) => {e.insert(d.value);}
+                        }
                     },
                     DI::SE(d) => {
-                        match d.setTable(l) {
+                        match d.setTable(&mut self.settings, l) {
                             Ok(_) => (),
                             Err(e) => errors += &e
                         }
                     },
                     DI::ST(d) => {
+                        if self.datas_table.contains_key(&d.name) {
+                            errors += &format!("Line: {} - \"{}\" has already been defined\n", l, d.name);
+                            continue;
+                        }
                         self.datas_table.insert(d.name.clone(), self.datas.len());
-                        self.datas.append(&mut d.generateData(l));
+                        match d.generateData(&self.settings, l).and_then(|u| Ok((d.length(l)?, u))) {
+                            Ok((length, u)) => {
+                                self.sizes_table.insert(d.name.clone(), (length, u.len()));
+                                self.datas.append(&mut u.clone())
+                            },
+                            Err(e) => errors += &e
+                        }
+                    },
+                    DI::IN(d) => {
+                        if self.datas_table.contains_key(&d.name) {
+                            errors += &format!("Line: {} - \"{}\" has already been defined\n", l, d.name);
+                            continue;
+                        }
+                        self.datas_table.insert(d.name.clone(), self.datas.len());
+                        match d.generateData(&self.settings, &self.structs_table, l) {
+                            Ok(u) => {
+                                self.sizes_table.insert(d.name.clone(), (1, u.len()));
+                                self.datas.append(&mut u.clone())
+                            },
+                            Err(e) => errors += &e
+                        }
                     },
                     DI::VA(d) => {
+                        if self.datas_table.contains_key(&d.name) {
+                            errors += &format!("Line: {} - \"{}\" has already been defined\n", l, d.name);
+                            continue;
+                        }
                         self.datas_table.insert(d.name.clone(), self.datas.len());
-                        match d.generateData(l) {
+                        match d.generateData(&self.settings, l) {
                             Ok(u) => self.datas.append(&mut u.clone()),
                             Err(e) => errors += &e
                         }
@@ -119,41 +242,50 @@ impl DIProcessor {
         }
     }
 
-    async fn start(self) -> Result<(usize, DI), String> {
+    fn start(self) -> Result<(usize, DI), String> {
         if self.line.starts_with(".SET") {
             match self.pset(self.line.trim_start_matches(".SET")) {
-                Ok(v) => return Ok((self.line_num, DI::SE(v))),
-                Err(e) => return Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
-            };
+                Ok(v) => Ok((self.line_num, DI::SE(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+            }
+        } else if self.line.starts_with(".VAR") && self.line.contains("{") {
+            match self.pinstance(self.line.trim_start_matches(".VAR")) {
+                Ok(v) => Ok((self.line_num, DI::IN(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+            }
         } else if self.line.starts_with(".VAR") {
             match self.pvar(self.line.trim_start_matches(".VAR")) {
-                Ok(v) => return Ok((self.line_num, DI::VA(v))),
-                Err(e) => return Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+                Ok(v) => Ok((self.line_num, DI::VA(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+            }
+        } else if self.line.starts_with(".PSTR") {
+            match self.pstr(self.line.trim_start_matches(".PSTR"), true) {
+                Ok(v) => Ok((self.line_num, DI::ST(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
             }
         } else if self.line.starts_with(".STR") {
-            match self.pstr(self.line.trim_start_matches(".STR")) {
-                Ok(v) => return Ok((self.line_num, DI::ST(v))),
-                Err(e) => return Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+            match self.pstr(self.line.trim_start_matches(".STR"), false) {
+                Ok(v) => Ok((self.line_num, DI::ST(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
             }
         } else if self.line.starts_with(".ARR") {
             match self.parr(self.line.trim_start_matches(".ARR")) {
-                Ok(v) => return Ok((self.line_num, DI::AR(v))),
-                Err(e) => return Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+                Ok(v) => Ok((self.line_num, DI::AR(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
             }
         } else if self.line.starts_with(".DEF") {
             match self.pdef(self.line.trim_start_matches(".DEF")) {
-                Ok(v) => return Ok((self.line_num, DI::DE(v))),
-                Err(e) => return Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
+                Ok(v) => Ok((self.line_num, DI::DE(v))),
+                Err(e) => Err(format!("Line: {} - \"{}\"\n", self.line_num, e))
             }
         } else {
-            return Err(format!("Line: {} - \"{}\" not a legal preprocessing command", self.line_num, self.line));
+            Err(format!("Line: {} - \"{}\" not a legal preprocessing command", self.line_num, self.line))
         }
     }
 
     fn pset(&self, args: &str) -> Result<SET, String> {
         let mut farg = String::new();
         let mut sarg = String::new();
-        let mut nvs = false;
 
         const FIRST_CHAR: u8 = 0;
         const FIRST_ARG: u8 = 1;
@@ -161,18 +293,18 @@ impl DIProcessor {
         const SECOND_ARG: u8 = 3;
 
         let mut curr_state = FIRST_CHAR;
-        for c in args.chars() {
+        for c in args.trim_start().chars() {
             match curr_state {
                 FIRST_CHAR => {
                     if !c.is_ascii_digit() {
                         farg.push(c);
                         curr_state = FIRST_ARG;
                     } else {
-                        return Err(format!("The first character cannot be a number"));
+                        return Err(String::from("The first character cannot be a number"));
                     }
                 },
                 FIRST_ARG => {
-                    if c != '\t' || c != ' ' {
+                    if c != '\t' && c != ' ' {
                         farg.push(c);
                     } else {
                         curr_state = BLANK;
@@ -180,13 +312,13 @@ impl DIProcessor {
                 },
                 BLANK => {
                     if c == '\n' {
-                        nvs = true;
                         break;
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        sarg.push(c);
                         curr_state = SECOND_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 SECOND_ARG => {
@@ -196,10 +328,10 @@ impl DIProcessor {
                         break;
                     }
                 },
-                _ => return Err(format!("State machine exception"))
+                _ => return Err(String::from("State machine exception"))
             }
         }
-        return Ok(SET::new(farg, sarg, nvs));
+        Ok(SET::new(farg, sarg))
     }
 
     fn pvar(&self, args: &str) -> Result<VAR, String> {
@@ -215,18 +347,18 @@ impl DIProcessor {
         const THIRD_ARG: u8 = 5;
 
         let mut curr_state = FIRST_CHAR;
-        for c in args.chars() {
+        for c in args.trim_start().chars() {
             match curr_state {
                 FIRST_CHAR => {
                     if !c.is_ascii_digit() {
                         farg.push(c);
                         curr_state = FIRST_ARG;
                     } else {
-                        return Err(format!("The first character cannot be a number"));
+                        return Err(String::from("The first character cannot be a number"));
                     }
                 },
                 FIRST_ARG => {
-                    if c != '\t' || c != ' ' {
+                    if c != '\t' && c != ' ' {
                         farg.push(c);
                     } else {
                         curr_state = FIRST_BLANK;
@@ -236,15 +368,16 @@ impl DIProcessor {
                     if c == '\n' {
                         break;
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        sarg.push(c);
                         curr_state = SECOND_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 SECOND_ARG => {
-                    if c != '\n' && (c != '\t' || c != ' ') {
-                        farg.push(c);
+                    if c != '\n' && c != '\t' && c != ' ' {
+                        sarg.push(c);
                     } else if c == '\n' {
                         break;
                     } else {
@@ -255,10 +388,11 @@ impl DIProcessor {
                     if c == '\n' {
                         break;
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        targ.push(c);
                         curr_state = THIRD_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 THIRD_ARG => {
@@ -268,7 +402,7 @@ impl DIProcessor {
                         break;
                     }
                 },
-                _ => return Err(format!("State machine exception"))
+                _ => return Err(String::from("State machine exception"))
             }
         }
 
@@ -278,17 +412,46 @@ impl DIProcessor {
             farg = String::from("dword");
         }
 
-        return Ok(VAR::new(farg, sarg, targ));
+        Ok(VAR::new(sarg, farg, targ))
+    }
+
+    // "TYPE name {field=value, ...}" of a struct TYPE
+    fn pinstance(&self, args: &str) -> Result<INSTANCE, String> {
+        let (head, value) = match args.split_once("{") {
+            Some((h, v)) => (h, v.trim_end()),
+            None => return Err(String::from("No initial values available"))
+        };
+        let value = match value.strip_suffix("}") {
+            Some(v) => v,
+            None => return Err(String::from("The initial values have no closing }"))
+        };
+        let head = head.split_whitespace().collect::<Vec<_>>();
+        if head.len() != 2 {
+            return Err(String::from("An instance should look like \".VAR PACKET pkt {id=1, len=4}\""));
+        }
+        if head[1].starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(String::from("The first character cannot be a number"));
+        }
+        Ok(INSTANCE::new(String::from(head[1]), String::from(head[0]), String::from(value)))
     }
 
-    fn pstr(&self, args: &str) -> Result<STR, String> {
+    // "[encoding] name "text"", escapes stay in the text until STR decodes them
+    fn pstr(&self, args: &str, prefixed: bool) -> Result<STR, String> {
         let mut farg = String::new();
         let mut sarg = String::new();
 
+        let args = args.trim_start();
+        let (encoding, args) = match args.split_once([' ', '\t']) {
+            Some((e, rest)) if STR_ENCODINGS.contains(&e) => (e, rest.trim_start()),
+            _ => ("utf8", args)
+        };
+
         const FIRST_CHAR: u8 = 0;
         const FIRST_ARG: u8 = 1;
         const BLANK: u8 = 2;
         const SECOND_ARG: u8 = 3;
+        const ESCAPE: u8 = 4;
+        const END: u8 = 5;
 
         let mut curr_state = FIRST_CHAR;
         for c in args.chars() {
@@ -298,11 +461,11 @@ impl DIProcessor {
                         farg.push(c);
                         curr_state = FIRST_ARG;
                     } else {
-                        return Err(format!("The first character cannot be a number"));
+                        return Err(String::from("The first character cannot be a number"));
                     }
                 },
                 FIRST_ARG => {
-                    if c != '\t' || c != ' ' {
+                    if c != '\t' && c != ' ' {
                         farg.push(c);
                     } else {
                         curr_state = BLANK;
@@ -310,25 +473,40 @@ impl DIProcessor {
                 },
                 BLANK => {
                     if c == '\n' {
-                        return Err(format!("No strings available"))
+                        return Err(String::from("No strings available"))
                     } else if c == '\"' {
                         curr_state = SECOND_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 SECOND_ARG => {
-                    if c != '\"' {
+                    if c == '\\' {
+                        sarg.push(c);
+                        curr_state = ESCAPE;
+                    } else if c != '\"' {
                         sarg.push(c);
                     } else {
-                        break;
+                        curr_state = END;
+                    }
+                },
+                ESCAPE => {
+                    sarg.push(c);
+                    curr_state = SECOND_ARG;
+                },
+                END => {
+                    if c != ' ' && c != '\t' && c != '\n' {
+                        return Err(String::from("Unusual string after the closing \""));
                     }
                 },
-                _ => return Err(format!("State machine exception"))
+                _ => return Err(String::from("State machine exception"))
             }
         }
-        return Ok(STR::new(farg, sarg));
+        if curr_state != END {
+            return Err(String::from("The string has no closing \""));
+        }
+        Ok(STR::new(farg, String::from(encoding), sarg, prefixed))
     }
 
     fn parr(&self, args: &str) -> Result<ARR, String> {
@@ -343,19 +521,19 @@ impl DIProcessor {
         const SECOND_BLANK: u8 = 4;
         const THIRD_ARG: u8 = 5;
 
-                let mut curr_state = FIRST_CHAR;
-        for c in args.chars() {
+        let mut curr_state = FIRST_CHAR;
+        for c in args.trim_start().chars() {
             match curr_state {
                 FIRST_CHAR => {
                     if !c.is_ascii_digit() {
                         farg.push(c);
                         curr_state = FIRST_ARG;
                     } else {
-                        return Err(format!("The first character cannot be a number"));
+                        return Err(String::from("The first character cannot be a number"));
                     }
                 },
                 FIRST_ARG => {
-                    if c != '\t' || c != ' ' {
+                    if c != '\t' && c != ' ' {
                         farg.push(c);
                     } else {
                         curr_state = FIRST_BLANK;
@@ -365,15 +543,16 @@ impl DIProcessor {
                     if c == '\n' {
                         break;
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        sarg.push(c);
                         curr_state = SECOND_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 SECOND_ARG => {
-                    if c != '\n' && (c != '\t' || c != ' ') {
-                        farg.push(c);
+                    if c != '\n' && c != '\t' && c != ' ' {
+                        sarg.push(c);
                     } else if c == '\n' {
                         break;
                     } else {
@@ -384,10 +563,11 @@ impl DIProcessor {
                     if c == '\n' {
                         break;
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        targ.push(c);
                         curr_state = THIRD_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 THIRD_ARG => {
@@ -397,7 +577,7 @@ impl DIProcessor {
                         break;
                     }
                 },
-                _ => return Err(format!("State machine exception"))
+                _ => return Err(String::from("State machine exception"))
             }
         }
 
@@ -407,7 +587,7 @@ impl DIProcessor {
             farg = String::from("dword");
         }
 
-        return Ok(ARR::new(farg, sarg, targ));
+        Ok(ARR::new(sarg, farg, targ))
     }
 
     fn pdef(&self, args: &str) -> Result<DEF, String> {
@@ -420,18 +600,18 @@ impl DIProcessor {
         const SECOND_ARG: u8 = 3;
 
         let mut curr_state = FIRST_CHAR;
-        for c in args.chars() {
+        for c in args.trim_start().chars() {
             match curr_state {
                 FIRST_CHAR => {
                     if !c.is_ascii_digit() {
                         farg.push(c);
                         curr_state = FIRST_ARG;
                     } else {
-                        return Err(format!("The first character cannot be a number"));
+                        return Err(String::from("The first character cannot be a number"));
                     }
                 },
                 FIRST_ARG => {
-                    if c != '\t' || c != ' ' {
+                    if c != '\t' && c != ' ' {
                         farg.push(c);
                     } else {
                         curr_state = BLANK;
@@ -439,12 +619,13 @@ impl DIProcessor {
                 },
                 BLANK => {
                     if c == '\n' {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     } else if c != '\n' && c != ' ' && c != '\t' {
+                        sarg.push(c);
                         curr_state = SECOND_ARG;
                     } else if c != '\n' && (c == ' ' || c == '\t') {
                     } else {
-                        return Err(format!("Unusual string"));
+                        return Err(String::from("Unusual string"));
                     }
                 },
                 SECOND_ARG => {
@@ -454,9 +635,9 @@ impl DIProcessor {
                         break;
                     }
                 },
-                _ => return Err(format!("State machine exception"))
+                _ => return Err(String::from("State machine exception"))
             }
         }
-        return Ok(DEF {name: farg, value: sarg});
+        Ok(DEF {name: farg, value: sarg})
     }
 }
\ No newline at end of file
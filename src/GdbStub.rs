@@ -0,0 +1,506 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+
+use crate::MCAssembler::REGISTERS;
+use crate::Simulator::{Simulator, StopReason, MEMORY_SIZE, PC};
+
+// how many instructions run between two checks for a Ctrl-C from GDB
+const CONTINUE_CHUNK: usize = 10_000;
+
+/// GDB remote serial protocol stub for the simulator
+///
+/// GDB addresses memory in bytes while the simulator works on 16-bit words,
+/// so every address in a packet, and the value of %PC, is a byte address
+/// (twice the word address). %PC is a 32-bit register for GDB because the
+/// byte address of a word at 8000H or above doesn't fit in 16 bits. Words in
+/// memory and register values are both sent in the byte order of the image
+/// (GDB has to be told with "set endian"), registers in the numbering of
+/// REGISTERS.
+pub struct GdbStub {
+    simulator: Simulator,
+    big_endian: bool,
+    // word addresses
+    breakpoints: Vec<u16>
+}
+
+/// The connection to GDB, which can be asked for a Ctrl-C while the program runs
+pub trait Connection: Read + Write {
+    /// Whether GDB sent its interrupt request, without waiting for one
+    fn interrupted(&mut self) -> std::io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn interrupted(&mut self) -> std::io::Result<bool> {
+        // a single 0x03 byte is GDB's interrupt request
+        self.set_nonblocking(true)?;
+        let mut buf = [0u8; 1];
+        let interrupted = match self.read(&mut buf) {
+            Ok(1) => buf[0] == 0x03,
+            Ok(_) => false,
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => false,
+            Err(e) => return Err(e)
+        };
+        self.set_nonblocking(false)?;
+        Ok(interrupted)
+    }
+}
+
+impl GdbStub {
+    /// `endian` is the byte order of the image, "little" or "big"
+    pub fn new(simulator: Simulator, endian: &str) -> GdbStub {
+        GdbStub {
+            simulator,
+            big_endian: endian == "big",
+            breakpoints: vec![]
+        }
+    }
+
+    /// Wait for one GDB connection on localhost and serve it until it detaches
+    pub fn listen(&mut self, port: u16) -> std::io::Result<()> {
+        let listener = TcpListener::bind(("127.0.0.1", port))?;
+        println!("[INFO] Waiting for GDB on 127.0.0.1:{}", port);
+        let (mut stream, addr) = listener.accept()?;
+        println!("[INFO] GDB connected from {}", addr);
+
+        self.serve(&mut stream)?;
+        println!("[INFO] GDB disconnected");
+        Ok(())
+    }
+
+    /// Answer the packets of GDB until it detaches or closes the connection
+    pub fn serve<C: Connection>(&mut self, stream: &mut C) -> std::io::Result<()> {
+        while let Some(packet) = read_packet(stream)? {
+            let reply = match self.handle(&packet, stream)? {
+                Some(r) => r,
+                None => {
+                    write_packet(stream, "OK")?;
+                    break;
+                }
+            };
+            write_packet(stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    // returns None when the connection should be closed
+    fn handle<C: Connection>(&mut self, packet: &str, stream: &mut C) -> std::io::Result<Option<String>> {
+        let reply = match packet.chars().next() {
+            Some('?') => String::from("S05"),
+            Some('g') => (0..REGISTERS.len()).map(|i| self.register_hex(i)).collect::<String>(),
+            Some('G') => {
+                let mut values = &packet[1..];
+                for i in 0..REGISTERS.len() {
+                    let width = register_width(i);
+                    match values.get(..width).and_then(|v| parse_register(i, v, self.big_endian)) {
+                        Some(v) => self.set_register(i, v),
+                        None => break
+                    }
+                    values = &values[width..];
+                }
+                String::from("OK")
+            },
+            Some('p') => match usize::from_str_radix(&packet[1..], 16) {
+                Ok(i) if i < REGISTERS.len() => self.register_hex(i),
+                _ => String::from("E01")
+            },
+            Some('P') => match packet[1..].split_once('=') {
+                Some((i, v)) => match usize::from_str_radix(i, 16).ok().filter(|i| *i < REGISTERS.len()).and_then(|i| Some((i, parse_register(i, v, self.big_endian)?))) {
+                    Some((i, v)) => {
+                        self.set_register(i, v);
+                        String::from("OK")
+                    },
+                    _ => String::from("E01")
+                },
+                None => String::from("E01")
+            },
+            // memory has MEMORY_SIZE words, twice as many bytes
+            Some('m') => match parse_addr_len(&packet[1..]) {
+                Some((addr, len)) if in_memory(addr, len) => (addr..addr + len).map(|b| format!("{:02x}", self.read_byte(b))).collect::<String>(),
+                _ => String::from("E01")
+            },
+            Some('M') => match packet[1..].split_once(':') {
+                Some((addr_len, data)) => match parse_addr_len(addr_len) {
+                    Some((addr, len)) if in_memory(addr, len) && data.len() == len * 2 => {
+                        let mut error = false;
+                        for (i, hex) in data.as_bytes().chunks(2).enumerate() {
+                            match std::str::from_utf8(hex).ok().and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                                Some(v) => self.write_byte(addr + i, v),
+                                None => error = true
+                            }
+                        }
+                        if error {String::from("E01")} else {String::from("OK")}
+                    },
+                    _ => String::from("E01")
+                },
+                None => String::from("E01")
+            },
+            Some('c') => {
+                if let Some(addr) = parse_hex(&packet[1..]) {
+                    self.simulator.set_pc((addr / 2) as u16);
+                }
+                self.resume(stream)?
+            },
+            Some('s') => {
+                if let Some(addr) = parse_hex(&packet[1..]) {
+                    self.simulator.set_pc((addr / 2) as u16);
+                }
+                match self.simulator.step() {
+                    Some(r) => stop_reply(r),
+                    None => String::from("S05")
+                }
+            },
+            Some('Z') | Some('z') => {
+                let args = packet[1..].split(',').collect::<Vec<_>>();
+                // software and hardware breakpoints are the same thing here
+                if args.len() == 3 && (args[0] == "0" || args[0] == "1") {
+                    match parse_hex(args[1]) {
+                        Some(addr) => {
+                            let addr = (addr / 2) as u16;
+                            if packet.starts_with('Z') {
+                                if !self.breakpoints.contains(&addr) {
+                                    self.breakpoints.push(addr);
+                                }
+                            } else {
+                                self.breakpoints.retain(|b| *b != addr);
+                            }
+                            String::from("OK")
+                        },
+                        None => String::from("E01")
+                    }
+                } else {
+                    String::new()
+                }
+            },
+            Some('H') => String::from("OK"),
+            Some('k') => return Ok(None),
+            Some('D') => return Ok(None),
+            Some('q') => {
+                if packet.starts_with("qSupported") {
+                    String::from("PacketSize=1000;qXfer:features:read+")
+                } else if let Some(annex) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+                    match parse_addr_len(annex) {
+                        Some((offset, len)) => {
+                            let xml = target_xml();
+                            if offset >= xml.len() {
+                                String::from("l")
+                            } else if offset.saturating_add(len) >= xml.len() {
+                                format!("l{}", &xml[offset..])
+                            } else {
+                                format!("m{}", &xml[offset..offset + len])
+                            }
+                        },
+                        None => String::from("E01")
+                    }
+                } else if packet == "qAttached" {
+                    String::from("1")
+                } else if packet == "qfThreadInfo" {
+                    String::from("m1")
+                } else if packet == "qsThreadInfo" {
+                    String::from("l")
+                } else if packet == "qC" {
+                    String::from("QC1")
+                } else {
+                    String::new()
+                }
+            },
+            // unsupported packets get an empty reply
+            _ => String::new()
+        };
+
+        Ok(Some(reply))
+    }
+
+    fn resume<C: Connection>(&mut self, stream: &mut C) -> std::io::Result<String> {
+        loop {
+            for _ in 0..CONTINUE_CHUNK {
+                if let Some(r) = self.simulator.step() {
+                    return Ok(stop_reply(r));
+                }
+                if self.breakpoints.contains(&self.simulator.pc()) {
+                    return Ok(String::from("S05"));
+                }
+            }
+
+            if stream.interrupted()? {
+                return Ok(String::from("S02"));
+            }
+        }
+    }
+
+    // register values are sent in target byte order, the byte order of the image
+    fn register_hex(&self, i: usize) -> String {
+        match (i == PC, self.big_endian) {
+            (true, true) => format!("{:08x}", self.simulator.pc() as u32 * 2),
+            (true, false) => format!("{:08x}", (self.simulator.pc() as u32 * 2).swap_bytes()),
+            (false, true) => format!("{:04x}", self.simulator.registers[i]),
+            (false, false) => format!("{:04x}", self.simulator.registers[i].swap_bytes())
+        }
+    }
+
+    fn set_register(&mut self, i: usize, value: u32) {
+        if i == PC {
+            self.simulator.set_pc((value / 2) as u16);
+        } else if i != 0 {
+            self.simulator.registers[i] = value as u16;
+        }
+    }
+
+    // the byte which comes first is the high byte of a big-endian word
+    fn high_byte(&self, addr: usize) -> bool {
+        addr.is_multiple_of(2) == self.big_endian
+    }
+
+    // `addr` must be in memory
+    fn read_byte(&self, addr: usize) -> u8 {
+        let word = self.simulator.memory[addr / 2];
+        if self.high_byte(addr) {(word >> 8) as u8} else {(word & 0xFF) as u8}
+    }
+
+    // `addr` must be in memory
+    fn write_byte(&mut self, addr: usize, value: u8) {
+        let high = self.high_byte(addr);
+        let word = &mut self.simulator.memory[addr / 2];
+        if high {
+            *word = (*word & 0x00FF) | ((value as u16) << 8);
+        } else {
+            *word = (*word & 0xFF00) | value as u16;
+        }
+    }
+}
+
+// returns None when the connection was closed
+fn read_packet<C: Connection>(stream: &mut C) -> std::io::Result<Option<String>> {
+    let mut byte = [0u8; 1];
+    loop {
+        // skip acks and anything else before the start of a packet
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = vec![];
+        loop {
+            if stream.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+        let mut checksum = [0u8; 2];
+        stream.read_exact(&mut checksum)?;
+
+        // the checksum is over the data as sent, before unescaping
+        let expected = u8::from_str_radix(&String::from_utf8_lossy(&checksum), 16).ok();
+        if expected == Some(checksum_of(&data)) {
+            stream.write_all(b"+")?;
+            return Ok(Some(String::from_utf8_lossy(&decode_packet(&data)).into_owned()));
+        } else {
+            stream.write_all(b"-")?;
+        }
+    }
+}
+
+fn write_packet<C: Connection>(stream: &mut C, data: &str) -> std::io::Result<()> {
+    stream.write_all(format!("${}#{:02x}", data, checksum_of(data.as_bytes())).as_bytes())?;
+    stream.flush()
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+    data.iter().fold(0u8, |s, b| s.wrapping_add(*b))
+}
+
+fn stop_reply(reason: StopReason) -> String {
+    match reason {
+        // the program stops for good on an interrupt, report it as an exit
+        StopReason::Interrupt(n) => format!("W{:02x}", n & 0xFF),
+        StopReason::IllegalInstruction(_) => String::from("S04"),
+        StopReason::StepLimit => String::from("S05")
+    }
+}
+
+fn target_xml() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n<target version=\"1.0\">\n<feature name=\"org.macpu.core\">\n");
+    for (i, name) in REGISTERS.iter().enumerate() {
+        let (reg_type, bits) = if i == PC {("code_ptr", 32)} else {("uint16", 16)};
+        xml += &format!("<reg name=\"{}\" bitsize=\"{}\" type=\"{}\" regnum=\"{}\"/>\n", name.to_lowercase(), bits, reg_type, i);
+    }
+    xml += "</feature>\n</target>\n";
+    xml
+}
+
+// number of hex digits of a register in a packet
+fn register_width(i: usize) -> usize {
+    if i == PC {8} else {4}
+}
+
+// value of register `i` from its hex digits in target byte order
+fn parse_register(i: usize, hex: &str, big_endian: bool) -> Option<u32> {
+    if hex.len() != register_width(i) {
+        return None;
+    }
+    let v = u32::from_str_radix(hex, 16).ok()?;
+    Some(match (i == PC, big_endian) {
+        (_, true) => v,
+        (true, false) => v.swap_bytes(),
+        (false, false) => (v as u16).swap_bytes() as u32
+    })
+}
+
+// data of a packet with "}x" escapes (x ^ 20H) and "c*n" runs (c repeated n -
+// 29 more times) expanded
+fn decode_packet(data: &[u8]) -> Vec<u8> {
+    let mut r: Vec<u8> = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        match data[i] {
+            b'}' if i + 1 < data.len() => {
+                r.push(data[i + 1] ^ 0x20);
+                i += 2;
+            },
+            b'*' if i + 1 < data.len() && !r.is_empty() => {
+                let last = r[r.len() - 1];
+                r.extend(std::iter::repeat_n(last, (data[i + 1] as usize).saturating_sub(29)));
+                i += 2;
+            },
+            b => {
+                r.push(b);
+                i += 1;
+            }
+        }
+    }
+    r
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn parse_addr_len(s: &str) -> Option<(usize, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    Some((parse_hex(addr)?, parse_hex(len)?))
+}
+
+// whether the `len` bytes at byte address `addr` are all in memory
+fn in_memory(addr: usize, len: usize) -> bool {
+    addr.checked_add(len).is_some_and(|end| end <= MEMORY_SIZE * 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // GDB's side of the connection, with the packets it sends in `input`
+    struct Pipe {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Connection for Pipe {
+        fn interrupted(&mut self) -> std::io::Result<bool> {
+            Ok(false)
+        }
+    }
+
+    fn packet(data: &str) -> String {
+        format!("${}#{:02x}", data, checksum_of(data.as_bytes()))
+    }
+
+    // the replies of a stub on `words` to the packets, without the acks
+    fn serve(words: &[u16], endian: &str, packets: &[&str]) -> Vec<String> {
+        let mut simulator = Simulator::new("docs/instructions.toml");
+        simulator.load_words(words);
+        let mut pipe = Pipe {
+            input: std::io::Cursor::new(packets.iter().map(|p| packet(p)).collect::<String>().into_bytes()),
+            output: vec![]
+        };
+        GdbStub::new(simulator, endian).serve(&mut pipe).unwrap();
+
+        let output = String::from_utf8(pipe.output).unwrap();
+        output.split('$').skip(1).map(|r| {
+            let (data, checksum) = r.split_once('#').unwrap();
+            assert_eq!(&checksum[..2], format!("{:02x}", checksum_of(data.as_bytes())));
+            String::from(data)
+        }).collect()
+    }
+
+    #[test]
+    fn checksums_are_the_sum_of_the_bytes() {
+        assert_eq!(packet("OK"), "$OK#9a");
+        assert_eq!(packet("g"), "$g#67");
+        assert_eq!(packet(""), "$#00");
+        assert_eq!(checksum_of(&[0xFF, 0x02]), 0x01);
+    }
+
+    #[test]
+    fn decode_packet_expands_escapes_and_runs() {
+        assert_eq!(decode_packet(b"m0,4"), b"m0,4");
+        // "}]" is "}" and "}\x03" is "#"
+        assert_eq!(decode_packet(b"}]}\x03"), b"}#");
+        // "0* " is "0" and 32 - 29 = 3 more zeros
+        assert_eq!(decode_packet(b"10* 2"), b"100002");
+        // a lone escape or a run with nothing to repeat is taken as it is
+        assert_eq!(decode_packet(b"*!a}"), b"*!a}");
+    }
+
+    #[test]
+    fn parse_register_takes_the_byte_order_of_the_image() {
+        assert_eq!(parse_register(1, "1234", true), Some(0x1234));
+        assert_eq!(parse_register(1, "1234", false), Some(0x3412));
+        assert_eq!(parse_register(PC, "00000010", true), Some(0x10));
+        assert_eq!(parse_register(PC, "10000000", false), Some(0x10));
+        assert_eq!(parse_register(1, "123", true), None);
+        assert_eq!(parse_register(PC, "1234", true), None);
+        assert_eq!(parse_register(1, "12x4", true), None);
+    }
+
+    #[test]
+    fn registers_and_memory_have_one_byte_order() {
+        let words = [0x1234, 0xABCD];
+        let replies = serve(&words, "big", &["P1=1234", "p1", "m0,4", "M0,2:5678", "m0,2", "p29", "k"]);
+        assert_eq!(replies, ["OK", "1234", "1234abcd", "OK", "5678", "00000000", "OK"]);
+
+        let replies = serve(&words, "little", &["P1=1234", "p1", "m0,4", "M0,2:5678", "m0,2", "P29=04000000", "p29", "k"]);
+        assert_eq!(replies, ["OK", "1234", "3412cdab", "OK", "5678", "OK", "04000000", "OK"]);
+    }
+
+    #[test]
+    fn reads_past_memory_are_errors() {
+        let replies = serve(&[], "big", &["m1fffe,2", "m1fffe,4", "mffffffffffffffff,2", "m0,ffffffffffffffff", "M1ffff,2:0000", "m0", "k"]);
+        assert_eq!(replies, ["0000", "E01", "E01", "E01", "E01", "E01", "OK"]);
+    }
+
+    #[test]
+    fn bad_packets_are_refused() {
+        let mut simulator = Simulator::new("docs/instructions.toml");
+        simulator.load_words(&[]);
+        // a bad checksum is answered with "-" and GDB sends the packet again
+        let mut pipe = Pipe {
+            input: std::io::Cursor::new(format!("+$g#00{}{}", packet("P0=x"), packet("D")).into_bytes()),
+            output: vec![]
+        };
+        GdbStub::new(simulator, "big").serve(&mut pipe).unwrap();
+        assert_eq!(String::from_utf8(pipe.output).unwrap(), format!("-+{}+{}", packet("E01"), packet("OK")));
+    }
+}
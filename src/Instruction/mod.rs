@@ -1 +0,0 @@
-pub mod BaseInstructions;
\ No newline at end of file
@@ -0,0 +1,766 @@
+extern crate taplo;
+
+use std::collections::HashMap;
+use std::{fs::File, io::Read};
+use taplo::parser::parse;
+use taplo::dom::node::{Key, Node};
+use taplo::dom::error::Error as DomError;
+
+pub struct Field {
+    pub name: String,
+    // index of the word this field lives in
+    pub word: usize,
+    pub start: u32,
+    pub width: u32,
+    // values are two's complement and sign-extended when read
+    pub signed: bool,
+    // labels are encoded as their distance from the next instruction
+    pub relative: bool,
+    // index of the argument encoded into this field
+    pub operand: Option<usize>
+}
+
+impl Field {
+    // widths of 64 bits or more (which check rejects) don't overflow the shift
+    pub fn mask(&self) -> u16 {
+        let bits = 1u64.checked_shl(self.width).unwrap_or(0).wrapping_sub(1);
+        bits.checked_shl(self.start).unwrap_or(0) as u16
+    }
+
+    pub fn extract(&self, words: &[u16]) -> u16 {
+        let value = (words[self.word] & self.mask()) >> self.start;
+        if self.signed && self.width > 0 && self.width < 16 && value >> (self.width - 1) != 0 {
+            value | (u16::MAX << self.width)
+        } else {
+            value
+        }
+    }
+
+    /// Smallest value the field can hold
+    pub fn min(&self) -> i64 {
+        if self.signed && self.width > 0 {
+            -(1i64 << (self.width.min(63) - 1))
+        } else {
+            0
+        }
+    }
+
+    /// Largest value the field can hold
+    pub fn max(&self) -> i64 {
+        if self.signed && self.width > 0 {
+            (1i64 << (self.width.min(63) - 1)) - 1
+        } else {
+            (1i64 << self.width.min(63)) - 1
+        }
+    }
+
+    /// Put `value` into the bits of this field, negative values in two's
+    /// complement. A value out of the range of the field is an error instead
+    /// of being cut
+    pub fn insert(&self, words: &mut [u16], value: i64) -> Result<(), String> {
+        // a signed field which isn't relative also takes its bits as an
+        // unsigned number, so -1 and 0FFFFH are the same 16-bit immediate
+        let max = if self.signed && !self.relative {(1i64 << self.width.min(63)) - 1} else {self.max()};
+        if value < self.min() || value > max {
+            let sign = if self.signed {"signed"} else {"unsigned"};
+            return Err(format!("value {} does not fit in {} field {} of {} bits (mask {:04X}H, {}..{})",
+                value, sign, self.name, self.width, self.mask(), self.min(), max));
+        }
+        words[self.word] |= (((value as u64) << self.start) as u16) & self.mask();
+        Ok(())
+    }
+}
+
+pub struct Format {
+    pub name: String,
+    pub fields: Vec<Field>,
+    // number of words of an instruction in this format
+    pub words: usize
+}
+
+impl Format {
+    pub fn opcode(&self) -> Option<&Field> {
+        self.fields.iter().find(|f| f.name == "opcode")
+    }
+
+    pub fn operand(&self, index: usize) -> Option<&Field> {
+        self.fields.iter().find(|f| f.operand == Some(index))
+    }
+}
+
+pub struct Variant {
+    pub kinds: Vec<String>,
+    // bcode plus the offset of this variant
+    pub code: u16
+}
+
+pub struct InstructionDef {
+    pub name: String,
+    pub arg_num: usize,
+    pub format: String,
+    pub variants: Vec<Variant>,
+    // instructions replacing this one when a relative label is out of reach
+    pub relax: Vec<String>,
+    // register class of each argument
+    pub classes: Vec<String>,
+    // arguments whose register is written
+    pub writes: Vec<usize>
+}
+
+/// A mnemonic which the assembler replaces with a sequence of instructions
+pub struct PseudoDef {
+    pub name: String,
+    pub arg_num: usize,
+    // only "kinds" is used
+    pub variants: Vec<Variant>,
+    pub expand: Vec<String>
+}
+
+/// The instruction set description read from `docs/instructions.toml`
+pub struct Isa {
+    pub word_width: u32,
+    pub kinds: Vec<String>,
+    // class name -> register name patterns
+    pub classes: HashMap<String, Vec<String>>,
+    pub formats: HashMap<String, Format>,
+    // in the order of the description
+    pub instructions: Vec<InstructionDef>,
+    pub pseudos: Vec<PseudoDef>
+}
+
+impl Isa {
+    pub fn load(isa_file_path: &str) -> Isa {
+        let mut isa_file = File::open(isa_file_path).unwrap();
+        let mut toml = String::new();
+        isa_file.read_to_string(&mut toml).unwrap();
+
+        match Isa::parse(&toml) {
+            Ok(isa) => isa,
+            Err(e) => panic!("[ERROR] Instruction set description {} can't be parsed:\n{}", isa_file_path, e)
+        }
+    }
+
+    pub fn parse(toml: &str) -> Result<Isa, String> {
+        let (isa, error_infos) = Isa::read(toml);
+        if error_infos.is_empty() {
+            Ok(isa)
+        } else {
+            Err(error_infos)
+        }
+    }
+
+    /// Check a description for problems which only show up while assembling
+    ///
+    /// Returns (errors, warnings), the errors include everything `parse`
+    /// rejects. Entries which can't be parsed are left out of the later checks.
+    pub fn check(toml: &str) -> (Vec<String>, Vec<String>) {
+        let mut errors = vec![];
+        let mut warnings = vec![];
+
+        let syntax = parse(toml);
+        for e in syntax.errors.iter() {
+            errors.push(format!("TOML syntax error at {:?}: {}", e.range, e.message));
+        }
+        if let Err(e) = syntax.into_dom().validate() {
+            for e in e {
+                errors.push(match e {
+                    DomError::ConflictingKeys {key, other} => format!("Key {} on line {} is already defined on line {}",
+                        key.value(), line_of(toml, &key), line_of(toml, &other)),
+                    e => format!("TOML error: {}", e)
+                });
+            }
+        }
+
+        let (isa, error_infos) = Isa::read(toml);
+        errors.extend(error_infos.lines().map(String::from));
+
+        for (i, inst) in isa.instructions.iter().enumerate() {
+            if isa.instructions[..i].iter().any(|other| other.name == inst.name) {
+                errors.push(format!("Premiter {} is defined more than once", inst.name));
+            }
+        }
+
+        for (name, format) in isa.formats.iter() {
+            let opcode_fields = format.fields.iter().filter(|f| f.name == "opcode").count();
+            if opcode_fields != 1 {
+                errors.push(format!("Format {} should have one \"opcode\" field, it has {}", name, opcode_fields));
+            }
+            let fits = |f: &Field| f.width > 0 && f.start + f.width <= isa.word_width && f.start + f.width <= 16;
+            for (i, field) in format.fields.iter().enumerate() {
+                if !fits(field) {
+                    errors.push(format!("Field {} of format {} (bits {}..{} of word {}) goes past the word width {}",
+                        field.name, name, field.start, field.start + field.width, field.word, isa.word_width));
+                    continue;
+                }
+                // fields which don't fit already have their error
+                for other in format.fields[..i].iter().filter(|f| fits(f)) {
+                    if other.word == field.word && other.mask() & field.mask() != 0 {
+                        errors.push(format!("Fields {} and {} of format {} overlap in word {} (mask {:04X})",
+                            other.name, field.name, name, field.word, other.mask() & field.mask()));
+                    }
+                }
+                if field.relative && !field.signed {
+                    warnings.push(format!("Field {} of format {} is relative but not signed, it can't reach backwards", field.name, name));
+                }
+                if let Some(operand) = field.operand {
+                    if format.fields[..i].iter().any(|f| f.operand == Some(operand)) {
+                        errors.push(format!("Format {} has more than one field for argument {}", name, operand));
+                    }
+                }
+            }
+        }
+
+        for inst in isa.instructions.iter() {
+            let format = match isa.formats.get(&inst.format) {
+                Some(f) => f,
+                None => {
+                    errors.push(format!("Premiter {} uses undefined format {}", inst.name, inst.format));
+                    continue;
+                }
+            };
+            for i in 0..inst.arg_num {
+                if format.operand(i).is_none() {
+                    errors.push(format!("Premiter {} has {} arguments, but format {} has no field for argument {}",
+                        inst.name, inst.arg_num, format.name, i));
+                }
+            }
+            if let Some(f) = format.opcode() {
+                for variant in inst.variants.iter() {
+                    if f.width < 16 && variant.code >> f.width != 0 {
+                        errors.push(format!("Premiter {} opcode {:04X}H does not fit in the {} bits of field opcode",
+                            inst.name, variant.code, f.width));
+                    }
+                }
+            }
+            if inst.classes.len() != inst.arg_num {
+                errors.push(format!("Premiter {} has {} arguments but {} \"arg_classes\"", inst.name, inst.arg_num, inst.classes.len()));
+            }
+            for class in inst.classes.iter() {
+                if !isa.classes.contains_key(class) {
+                    errors.push(format!("Premiter {} uses unknown register class {}", inst.name, class));
+                }
+            }
+            for w in inst.writes.iter() {
+                if *w >= inst.arg_num {
+                    errors.push(format!("Premiter {} writes argument {}, but it has {} arguments", inst.name, w, inst.arg_num));
+                }
+            }
+            for line in inst.relax.iter() {
+                check_template(&format!("Premiter {}", inst.name), line, inst.arg_num, &isa, &mut errors);
+            }
+            for variant in inst.variants.iter() {
+                for kind in variant.kinds.iter() {
+                    if !isa.kinds.contains(kind) {
+                        errors.push(format!("Premiter {} uses unknown argument kind {}", inst.name, kind));
+                    }
+                }
+            }
+        }
+
+        for pseudo in isa.pseudos.iter() {
+            if isa.instruction(&pseudo.name).is_some_and(|i| i.arg_num == pseudo.arg_num) {
+                errors.push(format!("Pseudo instruction {} has the name and the number of arguments of an instruction", pseudo.name));
+            }
+            for line in pseudo.expand.iter() {
+                check_template(&format!("Pseudo instruction {}", pseudo.name), line, pseudo.arg_num, &isa, &mut errors);
+            }
+            for variant in pseudo.variants.iter() {
+                for kind in variant.kinds.iter() {
+                    if !isa.kinds.contains(kind) {
+                        errors.push(format!("Pseudo instruction {} uses unknown argument kind {}", pseudo.name, kind));
+                    }
+                }
+            }
+        }
+
+        // every pair of variants which the decoder can't tell apart
+        let variants = isa.instructions.iter()
+            .flat_map(|i| i.variants.iter().map(move |v| (i, v)))
+            .collect::<Vec<_>>();
+        for (n, (inst, variant)) in variants.iter().enumerate() {
+            for (other_inst, other) in variants[..n].iter() {
+                let (f, other_f) = match (isa.opcode_field(inst), isa.opcode_field(other_inst)) {
+                    (Some(f), Some(o)) => (f, o),
+                    _ => continue
+                };
+                if f.word != other_f.word {
+                    continue;
+                }
+                let common = f.mask() & other_f.mask();
+                let bits = ((variant.code as u64) << f.start) as u16;
+                let other_bits = ((other.code as u64) << other_f.start) as u16;
+                if (bits ^ other_bits) & common != 0 {
+                    continue;
+                }
+
+                if other_inst.name != inst.name {
+                    errors.push(format!("Opcode {:04X}H of {} {} overlaps opcode {:04X}H of {} {}",
+                        variant.code, inst.name, variant.kinds.join(","),
+                        other.code, other_inst.name, other.kinds.join(",")));
+                } else if other.kinds == variant.kinds {
+                    errors.push(format!("Premiter {} defines the variant {} more than once", inst.name, variant.kinds.join(",")));
+                } else if other.kinds.iter().zip(variant.kinds.iter()).any(|(a, b)| a != b && !(is_address_kind(a) && is_address_kind(b))) {
+                    errors.push(format!("Premiter {} variants {} and {} share opcode {:04X}H",
+                        inst.name, other.kinds.join(","), variant.kinds.join(","), variant.code));
+                } else {
+                    // addresses and labels are both encoded as an address, such as JMP addr/label
+                    warnings.push(format!("Premiter {} variants {} and {} share opcode {:04X}H, it decodes as {}",
+                        inst.name, other.kinds.join(","), variant.kinds.join(","), variant.code, other.kinds.join(",")));
+                }
+            }
+        }
+
+        (errors, warnings)
+    }
+
+    // parses as much as it can, problems are collected in the returned string
+    fn read(toml: &str) -> (Isa, String) {
+        let toml = parse(toml).into_dom();
+        let mut error_infos = String::new();
+
+        let word_width = match get_int(&toml, "word_width", "the description") {
+            Ok(w) => w as u32,
+            Err(e) => {error_infos += &e; 0}
+        };
+
+        let kinds = match get_str_array(&toml.get("kinds")) {
+            Some(k) => k,
+            None => {error_infos += "The description dose not have \"kinds\", please check\n"; vec![]}
+        };
+
+        // register classes are optional, every register is in "all"
+        let mut classes = HashMap::new();
+        classes.insert(String::from("all"), vec![String::from("*")]);
+        if let Some(t) = toml.get("classes").as_table() {
+            for (key, patterns) in t.entries().get().iter() {
+                match get_str_array(patterns) {
+                    Some(p) => {classes.insert(String::from(key.value()), p);},
+                    None => error_infos += &format!("Register class {} can't be parsed, please check\n", key.value())
+                }
+            }
+        }
+
+        let mut formats = HashMap::new();
+        match toml.get("formats").as_table() {
+            Some(t) => for (key, format) in t.entries().get().iter() {
+                match parse_format(key.value(), format) {
+                    Ok(f) => {formats.insert(String::from(key.value()), f);},
+                    Err(e) => error_infos += &e
+                }
+            },
+            None => error_infos += "The description dose not have \"formats\", please check\n"
+        }
+
+        let mut instructions = vec![];
+        match toml.get("instructions").as_table() {
+            Some(t) => for (key, op_info) in t.entries().get().iter() {
+                match parse_instruction(key.value(), op_info) {
+                    Ok(i) => instructions.push(i),
+                    Err(e) => error_infos += &e
+                }
+            },
+            None => error_infos += "The description dose not have \"instructions\", please check\n"
+        }
+
+        // pseudo instructions are optional
+        let mut pseudos = vec![];
+        if let Some(t) = toml.get("pseudos").as_table() {
+            for (key, op_info) in t.entries().get().iter() {
+                match parse_pseudo(key.value(), op_info) {
+                    Ok(p) => pseudos.push(p),
+                    Err(e) => error_infos += &e
+                }
+            }
+        }
+
+        (Isa {
+            word_width,
+            kinds,
+            classes,
+            formats,
+            instructions,
+            pseudos
+        }, error_infos)
+    }
+
+    pub fn instruction(&self, name: &str) -> Option<&InstructionDef> {
+        self.instructions.iter().find(|i| i.name == name)
+    }
+
+    /// Whether the register `name` is in `class`, a pattern ending with "*"
+    /// matches every register starting with the rest of it
+    pub fn in_class(&self, class: &str, name: &str) -> bool {
+        match self.classes.get(class) {
+            Some(patterns) => patterns.iter().any(|p| match p.strip_suffix("*") {
+                Some(prefix) => name.starts_with(prefix),
+                None => p == name
+            }),
+            None => false
+        }
+    }
+
+    /// The pseudo instruction for `name` with `arg_num` arguments, one with the
+    /// name of an instruction is only used with its own number of arguments
+    pub fn pseudo(&self, name: &str, arg_num: usize) -> Option<&PseudoDef> {
+        self.pseudos.iter().find(|p| p.name == name && (p.arg_num == arg_num || self.instruction(name).is_none()))
+    }
+
+    pub fn format(&self, inst: &InstructionDef) -> &Format {
+        match self.formats.get(&inst.format) {
+            Some(f) => f,
+            None => panic!("[ERROR] Premiter {} uses undefined format {}", inst.name, inst.format)
+        }
+    }
+
+    fn opcode_field(&self, inst: &InstructionDef) -> Option<&Field> {
+        self.formats.get(&inst.format)?.opcode()
+    }
+
+    /// Length in words of the longest instruction
+    pub fn max_words(&self) -> usize {
+        self.formats.values().map(|f| f.words).max().unwrap_or(1)
+    }
+
+    /// Encode one variant of an instruction placed at `addr` with its
+    /// argument values, fails when a value is out of the range of its field
+    pub fn encode(&self, inst: &InstructionDef, variant: &Variant, args: &[i64], addr: u16) -> Result<Vec<u16>, String> {
+        let format = self.format(inst);
+        let mut words = vec![0u16; format.words];
+        let next = addr as i64 + format.words as i64;
+
+        if let Some(f) = format.opcode() {
+            f.insert(&mut words, variant.code as i64).map_err(|e| format!("Opcode of {}: {}", inst.name, e))?;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            let f = match format.operand(i) {
+                Some(f) => f,
+                None => panic!("[ERROR] Format {} has no field for argument {} of {}", format.name, i + 1, inst.name)
+            };
+            if f.relative && variant.kinds[i] == "label" {
+                f.insert(&mut words, *arg - next).map_err(|e| format!("Argument {} of {}: target {:04X}H is {} words away from the next instruction at {:04X}H, {}",
+                    i + 1, inst.name, arg, arg - next, next, e))?;
+            } else {
+                f.insert(&mut words, *arg).map_err(|e| format!("Argument {} of {}: {}", i + 1, inst.name, e))?;
+            }
+        }
+
+        Ok(words)
+    }
+
+    /// Whether every relative label of the instruction at `addr` is in the
+    /// range of its field
+    pub fn reaches(&self, inst: &InstructionDef, variant: &Variant, args: &[i64], addr: u16) -> bool {
+        let format = self.format(inst);
+        let next = addr as i64 + format.words as i64;
+        args.iter().enumerate().all(|(i, arg)| match format.operand(i) {
+            Some(f) if f.relative && variant.kinds[i] == "label" => *arg - next >= f.min() && *arg - next <= f.max(),
+            _ => true
+        })
+    }
+
+    /// Find the instruction starting at `words[0]`, returns it with the
+    /// matching variant and the argument values
+    pub fn decode(&self, words: &[u16]) -> Option<(&InstructionDef, &Variant, Vec<u16>)> {
+        for inst in self.instructions.iter() {
+            let format = match self.formats.get(&inst.format) {
+                Some(f) => f,
+                None => continue
+            };
+            if words.len() < format.words {
+                continue;
+            }
+            let opcode = match format.opcode() {
+                Some(f) => f.extract(words),
+                None => continue
+            };
+
+            // variants which share one opcode (such as JMP addr/label) decode as the first one
+            if let Some(variant) = inst.variants.iter().find(|v| v.code == opcode) {
+                let args = (0..inst.arg_num).map(|i| match format.operand(i) {
+                        Some(f) => f.extract(words),
+                        None => 0
+                    }).collect::<Vec<_>>();
+                return Some((inst, variant, args));
+            }
+        }
+
+        None
+    }
+}
+
+fn parse_format(name: &str, format: &Node) -> Result<Format, String> {
+    let items = match format.get("fields").as_array() {
+        Some(a) => a.items().get(),
+        None => return Err(format!("Format {} dose not have \"fields\", please check\n", name))
+    };
+
+    let mut fields = vec![];
+    for field in items.iter() {
+        let field_name = match field.get("name").as_str() {
+            Some(s) => String::from(s.value()),
+            None => return Err(format!("A field of format {} dose not have \"name\", please check\n", name))
+        };
+        let context = format!("field {} of format {}", field_name, name);
+        let operand = match field.get("operand").as_integer() {
+            Some(_) => Some(get_int(field, "operand", &context)? as usize),
+            None => None
+        };
+        let signed = match field.get("signed").as_bool() {
+            Some(b) => b.value(),
+            None => false
+        };
+        let relative = match field.get("relative").as_bool() {
+            Some(b) => b.value(),
+            None => false
+        };
+        fields.push(Field {
+            name: field_name,
+            word: get_int(field, "word", &context)? as usize,
+            start: get_int(field, "start", &context)? as u32,
+            width: get_int(field, "width", &context)? as u32,
+            signed,
+            relative,
+            operand
+        });
+    }
+
+    let words = fields.iter().map(|f| f.word + 1).max().unwrap_or(1);
+    Ok(Format {
+        name: String::from(name),
+        fields,
+        words
+    })
+}
+
+fn parse_instruction(op: &str, op_info: &Node) -> Result<InstructionDef, String> {
+    let context = format!("Premiter {}", op);
+    let bcode = match u16::try_from(get_int(op_info, "bcode", &context)?) {
+        Ok(b) => b,
+        Err(_) => return Err(format!("Premiter {} info \"bcode\" is wider than 16 bits, please check\n", op))
+    };
+    let arg_num = get_int(op_info, "arg_num", &context)? as usize;
+    let format = match op_info.get("format").as_str() {
+        Some(s) => String::from(s.value()),
+        None => return Err(format!("Premiter {} info dose not have \"format\", please check\n", op))
+    };
+
+    let variants = parse_variants(op, op_info, arg_num, bcode)?;
+
+    let relax = match op_info.get("relax") {
+        Node::Invalid(_) => vec![],
+        n => match get_str_array(&n) {
+            Some(r) => r,
+            None => return Err(format!("Premiter {} info \"relax\" can't be parsed, please check\n", op))
+        }
+    };
+
+    let classes = match op_info.get("arg_classes") {
+        Node::Invalid(_) => vec![String::from("all"); arg_num],
+        n => match get_str_array(&n) {
+            Some(c) => c,
+            None => return Err(format!("Premiter {} info \"arg_classes\" can't be parsed, please check\n", op))
+        }
+    };
+    let writes = match op_info.get("writes").as_array() {
+        Some(a) => {
+            let mut writes = vec![];
+            for w in a.items().get().iter() {
+                match w.as_integer().and_then(|i| i.value().as_positive()) {
+                    Some(i) => writes.push(i as usize),
+                    None => return Err(format!("Premiter {} info \"writes\" can't be parsed, please check\n", op))
+                }
+            }
+            writes
+        },
+        None => vec![]
+    };
+
+    Ok(InstructionDef {
+        name: String::from(op),
+        arg_num,
+        format,
+        variants,
+        relax,
+        classes,
+        writes
+    })
+}
+
+// both are encoded as the address they stand for
+fn is_address_kind(kind: &str) -> bool {
+    kind == "addr" || kind == "label"
+}
+
+fn line_of(toml: &str, key: &Key) -> usize {
+    match key.text_ranges().next() {
+        Some(r) => toml[..usize::from(r.start())].matches('\n').count() + 1,
+        None => 0
+    }
+}
+
+fn parse_variants(op: &str, op_info: &Node, arg_num: usize, bcode: u16) -> Result<Vec<Variant>, String> {
+    let mut variants = vec![];
+    if arg_num == 0 {
+        variants.push(Variant {kinds: vec![], code: bcode});
+    } else {
+        let arg_kinds = match op_info.get("arg_kinds").as_array() {
+            Some(a) => a.items().get(),
+            None => return Err(format!("Premiter {} info dose not have \"arg_kinds\", please check\n", op))
+        };
+        for kinds in arg_kinds.iter() {
+            let kinds = match get_str_array(kinds) {
+                Some(k) => k,
+                None => return Err(format!("Premiter {} info argument(s) in \"arg_kinds\"can't be parsed, please check\n", op))
+            };
+            // the optional last element is the offset of this variant from "bcode"
+            if kinds.len() == arg_num + 1 {
+                match kinds[arg_num].parse::<u16>().ok().and_then(|offset| bcode.checked_add(offset)) {
+                    Some(code) => variants.push(Variant {kinds: kinds[0..arg_num].to_vec(), code}),
+                    None => return Err(format!("Premiter {} info variant offset {} can't be parsed, please check\n", op, kinds[arg_num]))
+                }
+            } else if kinds.len() == arg_num {
+                variants.push(Variant {kinds, code: bcode});
+            } else {
+                return Err(format!("Premiter {} info \"arg_kinds\" element [{}] has {} kinds, but \"arg_num\" is {}\n",
+                    op, kinds.join(", "), kinds.len(), arg_num));
+            }
+        }
+    }
+    Ok(variants)
+}
+
+fn parse_pseudo(op: &str, op_info: &Node) -> Result<PseudoDef, String> {
+    let context = format!("Pseudo instruction {}", op);
+    let arg_num = get_int(op_info, "arg_num", &context)? as usize;
+    let variants = parse_variants(op, op_info, arg_num, 0)?;
+    let expand = match get_str_array(&op_info.get("expand")) {
+        Some(e) if !e.is_empty() => e,
+        _ => return Err(format!("Pseudo instruction {} info dose not have \"expand\", please check\n", op))
+    };
+
+    Ok(PseudoDef {
+        name: String::from(op),
+        arg_num,
+        variants,
+        expand
+    })
+}
+
+// problems of a "relax" or "expand" line of `owner`, which has `arg_num` arguments
+fn check_template(owner: &str, line: &str, arg_num: usize, isa: &Isa, errors: &mut Vec<String>) {
+    let op = line.split(" ").next().unwrap_or("");
+    if isa.instruction(op).is_none() {
+        errors.push(format!("{} uses undefined instruction {} in \"{}\"", owner, op, line));
+    }
+    let mut rest = line;
+    while let Some(i) = rest.find("{") {
+        rest = &rest[i + 1..];
+        let name = rest.split("}").next().unwrap_or("");
+        let arg = name.trim_end_matches(".hi").trim_end_matches(".lo").trim_end_matches(".next");
+        if arg != "end" && arg.parse::<usize>().map_or(true, |n| n >= arg_num) {
+            errors.push(format!("{} uses unknown argument {{{}}} in \"{}\"", owner, name, line));
+        }
+    }
+}
+
+fn get_int(node: &Node, key: &str, context: &str) -> Result<u64, String> {
+    match node.get(key).as_integer() {
+        Some(i) => match i.value().as_positive() {
+            Some(v) => Ok(v),
+            None => Err(format!("{} info \"{}\" can't be parsed, please check\n", context, key))
+        },
+        None => Err(format!("{} info dose not have \"{}\", please check\n", context, key))
+    }
+}
+
+fn get_str_array(node: &Node) -> Option<Vec<String>> {
+    let items = node.as_array()?.items().get();
+    let mut r = vec![];
+    for i in items.iter() {
+        r.push(String::from(i.as_str()?.value()));
+    }
+    Some(r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(width: u32, signed: bool, relative: bool) -> Field {
+        Field {name: String::from("f"), word: 0, start: 4, width, signed, relative, operand: Some(0)}
+    }
+
+    #[test]
+    fn insert_rejects_values_out_of_range() {
+        let mut words = [0u16];
+        assert!(field(8, false, false).insert(&mut words, 255).is_ok());
+        assert_eq!(words[0], 0x0FF0);
+        assert!(field(8, false, false).insert(&mut words, 256).unwrap_err().contains("does not fit in unsigned field f of 8 bits"));
+        assert!(field(8, false, false).insert(&mut words, -1).is_err());
+
+        let mut words = [0u16];
+        assert!(field(8, true, true).insert(&mut words, -128).is_ok());
+        assert_eq!(words[0], 0x0800);
+        assert!(field(8, true, true).insert(&mut words, 128).unwrap_err().contains("-128..127"));
+        assert!(field(8, true, true).insert(&mut words, -129).is_err());
+
+        // signed fields which aren't relative take unsigned numbers too
+        let mut words = [0u16];
+        assert!(field(8, true, false).insert(&mut words, 255).is_ok());
+        assert!(field(8, true, false).insert(&mut words, 256).unwrap_err().contains("-128..255"));
+    }
+
+    #[test]
+    fn fields_of_any_width_have_a_range() {
+        let wide = Field {start: 0, ..field(64, true, false)};
+        assert_eq!(wide.mask(), 0xFFFF);
+        assert_eq!(field(0, true, false).min(), 0);
+        assert_eq!(field(0, true, false).max(), 0);
+        assert_eq!(field(0, false, false).mask(), 0);
+    }
+
+    #[test]
+    fn check_accepts_the_description() {
+        let toml = std::fs::read_to_string("docs/instructions.toml").unwrap();
+        let (errors, _) = Isa::check(&toml);
+        assert!(errors.is_empty(), "{:?}", errors);
+    }
+
+    #[test]
+    fn check_reports_bad_fields() {
+        let toml = r#"
+word_width = 16
+kinds = ["regs", "addr", "imdn", "label"]
+
+[formats.OP]
+fields = [
+    { name = "opcode", word = 0, start = 0, width = 16 }
+]
+
+[formats.BAD]
+fields = [
+    { name = "opcode", word = 0, start = 0, width = 8 },
+    { name = "a", word = 0, start = 4, width = 8, operand = 0 },
+    { name = "b", word = 0, start = 0, width = 64, operand = 1 }
+]
+
+[instructions.NOP]
+bcode = 0
+arg_num = 0
+format = "OP"
+
+[instructions.TWO]
+bcode = 1
+arg_num = 2
+format = "BAD"
+arg_kinds = [
+    ["regs", "regs"]
+]
+"#;
+        let (errors, _) = Isa::check(toml);
+        assert!(errors.iter().any(|e| e.starts_with("Fields opcode and a of format BAD overlap")), "{:?}", errors);
+        assert!(errors.iter().any(|e| e.starts_with("Field b of format BAD (bits 0..64 of word 0) goes past")), "{:?}", errors);
+        // the field which doesn't fit isn't checked for overlaps
+        assert!(!errors.iter().any(|e| e.contains("and b of format BAD")), "{:?}", errors);
+    }
+}
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::DotInstruction::BaseDInstructions::{DATA_TYPES, STR_ENCODINGS};
+use crate::Isa::Isa;
+use crate::MCAssembler::{part_register, source_lines, split_code, REGISTERS};
+
+/// Every check of the linter, with what it looks for
+pub const CHECKS: [(&str, &str); 5] = [
+    ("unused-label", "labels which no instruction uses"),
+    ("unused-symbol", "data symbols which are never referenced"),
+    ("shadowing-define", "defines named like a register or an instruction"),
+    ("unreachable", "code after an unconditional jump which no label leads to"),
+    ("fall-into-data", "labels whose code runs off the end of the code into the data")
+];
+
+// instructions after which the next line only runs when it has a label
+const UNCONDITIONAL: [&str; 3] = ["JMP", "BR", "RET"];
+// instructions which end a path through the code
+const ENDS: [&str; 4] = ["JMP", "BR", "RET", "INT"];
+
+pub struct Warning {
+    pub check: &'static str,
+    pub line_num: usize,
+    pub message: String
+}
+
+/// Semantic checks of an assembly file which don't need an image
+pub struct Linter {
+    isa: Isa,
+    enabled: Vec<&'static str>
+}
+
+impl Linter {
+    pub fn new(instructions_file_path: &str) -> Linter {
+        Linter {
+            isa: Isa::load(instructions_file_path),
+            enabled: CHECKS.iter().map(|(c, _)| *c).collect()
+        }
+    }
+
+    /// Turn checks on (`-W`) or off (`-A`), "all" stands for every check
+    pub fn set(&mut self, check: &str, enabled: bool) -> Result<(), String> {
+        let checks = if check == "all" {
+            CHECKS.iter().map(|(c, _)| *c).collect::<Vec<_>>()
+        } else {
+            match CHECKS.iter().find(|(c, _)| *c == check) {
+                Some((c, _)) => vec![*c],
+                None => return Err(format!("Unknown check {}, the checks are: all, {}", check,
+                    CHECKS.iter().map(|(c, _)| *c).collect::<Vec<_>>().join(", ")))
+            }
+        };
+        for c in checks {
+            self.enabled.retain(|e| *e != c);
+            if enabled {
+                self.enabled.push(c);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn lint(&self, asm: &str) -> Vec<Warning> {
+        let mut warnings = vec![];
+        // name -> line number
+        let mut labels: Vec<(&str, usize)> = vec![];
+        let mut datas: Vec<(&str, usize)> = vec![];
+        let mut defines: HashMap<&str, &str> = HashMap::new();
+        // (line number, op, args), labels are the lines ending with ":"
+        let mut code: Vec<(usize, &str, Vec<&str>)> = vec![];
+        let mut references: Vec<&str> = vec![];
+
+        // the lines are read like the assembler reads them
+        let mut in_struct = false;
+        for (line_num, line) in source_lines(asm) {
+            let items = line.split_whitespace().collect::<Vec<_>>();
+
+            // the fields of a .STRUCT are not code
+            if items[0] == ".STRUCT" {
+                in_struct = true;
+            } else if items[0] == ".ENDS" {
+                in_struct = false;
+                continue;
+            }
+            if in_struct {
+                continue;
+            }
+
+            match items[0] {
+                // ".VAR TYPE name {field=value}" of a struct
+                ".VAR" if items.len() > 2 && line.contains("{") => datas.push((items[2], line_num)),
+                // ".STR [encoding] name "text""
+                ".STR" | ".PSTR" if items.len() > 2 && STR_ENCODINGS.contains(&items[1]) => datas.push((items[2], line_num)),
+                // ".VAR type name value"
+                ".VAR" if items.len() > 2 && is_data_type(items[1]) => datas.push((items[2], line_num)),
+                ".DATA" | ".STRING" | ".VAR" | ".STR" | ".PSTR" if items.len() > 1 => datas.push((items[1], line_num)),
+                // ".ARR type name values"
+                ".ARR" if items.len() > 2 => datas.push((items[2], line_num)),
+                // ".EQU NAME [type] expr", the expression uses the constants in it
+                ".EQU" if items.len() > 1 => {
+                    datas.push((items[1], line_num));
+                    for item in items[2..].iter() {
+                        references.extend(item.split(|c: char| "+-*/%&|<>()".contains(c)).filter(|n| !n.is_empty()));
+                    }
+                },
+                ".DEFINE" | ".DEF" if items.len() > 2 => {
+                    defines.insert(items[1], items[2]);
+                    if self.shadows(items[1]) {
+                        warnings.push(Warning {
+                            check: "shadowing-define",
+                            line_num,
+                            message: format!("{} is also the name of a register or an instruction", items[1])
+                        });
+                    }
+                },
+                // ".EXPECT [NAME] == value" uses NAME
+                ".EXPECT" => references.extend(items[1..].iter().map(|i| i.trim_start_matches("[").trim_end_matches("]"))),
+                op if op.starts_with(".") => (),
+                op if op.ends_with(":") => {
+                    labels.push((op.trim_end_matches(":"), line_num));
+                    code.push((line_num, op, vec![]));
+                },
+                _ => {
+                    let (op, args) = split_code(line);
+                    code.push((line_num, op, args.unwrap_or_default()));
+                }
+            }
+        }
+
+        for (_, op, args) in code.iter() {
+            for arg in args.iter().chain(std::iter::once(op)) {
+                let name = size_of(arg.trim_start_matches("[").trim_end_matches("]").trim_start_matches("$"));
+                references.push(name);
+                if let Some(value) = defines.get(name) {
+                    references.push(value.trim_start_matches("[").trim_end_matches("]").trim_start_matches("$"));
+                }
+            }
+        }
+
+        for (name, line_num) in labels.iter() {
+            if !references.contains(name) {
+                warnings.push(Warning {
+                    check: "unused-label",
+                    line_num: *line_num,
+                    message: format!("Label {} is never used", name)
+                });
+            }
+        }
+        for (name, line_num) in datas.iter() {
+            if !references.contains(name) {
+                warnings.push(Warning {
+                    check: "unused-symbol",
+                    line_num: *line_num,
+                    message: format!("{} is never referenced", name)
+                });
+            }
+        }
+
+        // code after an unconditional jump only runs when a label leads to it
+        let mut after_jump: Option<usize> = None;
+        for (line_num, op, _) in code.iter() {
+            let op = defines.get(op).copied().unwrap_or(op);
+            if op.ends_with(":") {
+                after_jump = None;
+            } else if let Some(jump_line) = after_jump {
+                warnings.push(Warning {
+                    check: "unreachable",
+                    line_num: *line_num,
+                    message: format!("This line can't be reached, the jump on line {} always leaves", jump_line)
+                });
+                after_jump = None;
+            } else if UNCONDITIONAL.contains(&op) {
+                after_jump = Some(*line_num);
+            }
+        }
+
+        // the words after the last instruction are padding and data
+        let last = code.iter().rev().find(|(_, op, _)| !op.ends_with(":"));
+        let ends = match last {
+            Some((_, op, _)) => ENDS.contains(&defines.get(op).copied().unwrap_or(op)),
+            None => true
+        };
+        if !ends {
+            let last_line = last.map(|(l, _, _)| *l).unwrap_or(0);
+            if let Some((name, line_num)) = labels.iter().rev().find(|(_, l)| *l < last_line) {
+                warnings.push(Warning {
+                    check: "fall-into-data",
+                    line_num: *line_num,
+                    message: format!("The code of label {} runs into the data after line {}, end it with a jump or INT", name, last_line)
+                });
+            }
+        }
+        for (name, line_num) in labels.iter().filter(|(_, l)| last.is_none_or(|(last_line, _, _)| l > last_line)) {
+            warnings.push(Warning {
+                check: "fall-into-data",
+                line_num: *line_num,
+                message: format!("Label {} has no code after it, it points into the data", name)
+            });
+        }
+
+        warnings.retain(|w| self.enabled.contains(&w.check));
+        warnings.sort_by_key(|w| w.line_num);
+        warnings
+    }
+
+    fn shadows(&self, name: &str) -> bool {
+        let register = name.trim_start_matches("%");
+        REGISTERS.contains(&register) || part_register(register, 'A').is_some()
+            || self.isa.instruction(name).is_some() || self.isa.pseudos.iter().any(|p| p.name == name)
+    }
+}
+
+// a type of .VAR, such as word or q1.15
+fn is_data_type(name: &str) -> bool {
+    DATA_TYPES.contains(&name) || name.strip_prefix("q").is_some_and(|q| q.contains("."))
+}
+
+// the data a size symbol such as NAME.len or SIZEOF(NAME) is about, other
+// names stay the same
+fn size_of(name: &str) -> &str {
+    if let Some(n) = name.strip_suffix(".len").or(name.strip_suffix(".size")) {
+        n
+    } else if let Some(n) = name.strip_prefix("LENGTHOF(").or(name.strip_prefix("SIZEOF(")).and_then(|n| n.strip_suffix(")")) {
+        n.trim()
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // (check, line number) of each warning
+    fn lint(linter: &Linter, asm: &str) -> Vec<(&'static str, usize)> {
+        linter.lint(asm).iter().map(|w| (w.check, w.line_num)).collect()
+    }
+
+    #[test]
+    fn every_check_fires() {
+        let linter = Linter::new("docs/instructions.toml");
+        assert_eq!(lint(&linter, "UNUSED:\nNOP\nINT 0\n"), [("unused-label", 1)]);
+        assert_eq!(lint(&linter, ".DATA COUNT 0\n.VAR word TOTAL 0\nMOV COUNT,%A1\nINT 0\n"), [("unused-symbol", 2)]);
+        assert_eq!(lint(&linter, ".DEFINE A1 %A2\n.DEFINE MOV JMP\nINT 0\n"), [("shadowing-define", 1), ("shadowing-define", 2)]);
+        assert_eq!(lint(&linter, "LOOP:\nJMP LOOP\nNOP\nINT 0\n"), [("unreachable", 3)]);
+        assert_eq!(lint(&linter, "LOOP:\nINC %A1\nBNZ %A1,LOOP\n"), [("fall-into-data", 1)]);
+        assert_eq!(lint(&linter, "JMP END\nEND:\n"), [("fall-into-data", 2)]);
+    }
+
+    #[test]
+    fn lines_are_read_like_the_assembler_reads_them() {
+        let linter = Linter::new("docs/instructions.toml");
+        // comments, blanks around the commas and blank lines before the code
+        assert_eq!(lint(&linter, "\n\n.DATA  COUNT  0 ; the count\n  LOOP:  ; again\nMOV COUNT, %A1\nJMP   LOOP\n"), []);
+    }
+
+    #[test]
+    fn checks_are_turned_off_and_on() {
+        let asm = "UNUSED:\nJMP UNUSED2\nNOP\nUNUSED2:\nINT 0\n";
+        let mut linter = Linter::new("docs/instructions.toml");
+        assert_eq!(lint(&linter, asm), [("unused-label", 1), ("unreachable", 3)]);
+        linter.set("unreachable", false).unwrap();
+        assert_eq!(lint(&linter, asm), [("unused-label", 1)]);
+        linter.set("all", false).unwrap();
+        assert_eq!(lint(&linter, asm), []);
+        linter.set("unreachable", true).unwrap();
+        assert_eq!(lint(&linter, asm), [("unreachable", 3)]);
+        linter.set("all", true).unwrap();
+        assert_eq!(lint(&linter, asm), [("unused-label", 1), ("unreachable", 3)]);
+        assert!(linter.set("unused", true).unwrap_err().starts_with("Unknown check unused, the checks are: all, unused-label"));
+    }
+}
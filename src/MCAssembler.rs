@@ -1,56 +1,539 @@
-extern crate taplo;
-
 use std::collections::HashMap;
 use std::io::Write;
 use std::{fs::File, io::Read};
-use taplo::parser::parse;
-use taplo::dom::node::Node;
-use taplo::dom::Error;
-use taplo::dom::error;
+
+use crate::Cfg::Cfg;
+use crate::Disassembler::Disassembler;
+use crate::DotInstruction::DIProcessor::DotInstrctionsProcessor;
+use crate::Isa::{InstructionDef, Isa, Variant};
+use crate::StackUsage::{self, Depth};
+use crate::SymbolMap::SymbolMap;
+
+// Register numbering follows all_register, register operands are encoded as a
+// whole word with the highest bit set
+pub const REGISTER_FLAG: u16 = 0b1000_0000_0000_0000;
+pub const REGISTERS: [&str; 42] = [
+    "ZERO",
+    // Part A Register
+    "A1", "A2", "A3", "A4", "AR1", "AR2", "AR3", "ASS", "ASP", "ADS",
+    // Part B Register
+    "B1", "B2", "B3", "B4", "BR1", "BR2", "BR3", "BSS", "BSP", "BDS",
+    // Part C Register
+    "C1", "C2", "C3", "C4", "CR1", "CR2", "CR3", "CSS", "CSP", "CDS",
+    // Part D Register
+    "D1", "D2", "D3", "D4", "DR1", "DR2", "DR3", "DSS", "DSP", "DDS",
+    "PC"
+];
+
+// an address operand with the highest bit set would be read as a register, so
+// addresses and the segments stay below 8000H
+const ADDRESS_RANGE_ERROR: &str = "out of the addresses 0..7FFFH, the highest bit of an address marks a register";
+
+// label of the address after a "relax" or "expand" sequence
+const RELAX_END: &str = "{end}";
+
+// registers of each part in the order of REGISTERS, "R1" of part B is BR1
+pub const PARTS: [char; 4] = ['A', 'B', 'C', 'D'];
+const PART_REGISTERS: [&str; 10] = ["1", "2", "3", "4", "R1", "R2", "R3", "SS", "SP", "DS"];
+
+pub fn register_code(name: &str) -> Option<u16> {
+    REGISTERS.iter().position(|r| *r == name).map(|i| REGISTER_FLAG | i as u16)
+}
+
+pub fn register_name(code: u16) -> Option<&'static str> {
+    if code & REGISTER_FLAG == 0 {
+        None
+    } else {
+        REGISTERS.get((code & !REGISTER_FLAG) as usize).copied()
+    }
+}
+
+/// Physical name of a part-relative register, such as SP in `part` or B.SP
+/// in part B. Only the names with a letter can be used without a part.
+pub fn part_register(name: &str, part: char) -> Option<String> {
+    let (part, name) = match name.split_once(".") {
+        Some((p, n)) if p.len() == 1 => (p.chars().next()?, n),
+        Some(_) => return None,
+        None if !name.starts_with(|c: char| c.is_ascii_digit()) => (part, name),
+        None => return None
+    };
+    if PARTS.contains(&part) && PART_REGISTERS.contains(&name) {
+        Some(format!("{}{}", part, name))
+    } else {
+        None
+    }
+}
+
+/// The part a register belongs to, %ZERO and %PC belong to none
+pub fn register_part(code: u16) -> Option<char> {
+    let index = (code & !REGISTER_FLAG) as usize;
+    if index >= 1 && index <= PARTS.len() * PART_REGISTERS.len() {
+        Some(PARTS[(index - 1) / PART_REGISTERS.len()])
+    } else {
+        None
+    }
+}
+
+/// Convert `value` to the Q format `format`, such as "q1.15" (one integer bit,
+/// which is the sign, and 15 fraction bits), with rounding. Returns the value in
+/// two's complement, the number of bits and notes on saturation or lost precision
+pub fn fixed_point(format: &str, value: &str) -> Result<(i64, u32, Vec<String>), String> {
+    let (m, n) = match format.strip_prefix("q").and_then(|q| q.split_once(".")) {
+        Some((m, n)) => match (m.parse::<u32>(), n.parse::<u32>()) {
+            (Ok(m), Ok(n)) if m >= 1 => (m, n),
+            _ => return Err(format!("{} is not a Q format, write it like q1.15", format))
+        },
+        None => return Err(format!("{} is not a Q format, write it like q1.15", format))
+    };
+    let bits = m + n;
+    if ![8, 16, 32, 64].contains(&bits) {
+        return Err(format!("{} has {} bits, a Q format needs 8, 16, 32 or 64", format, bits));
+    }
+    let v = match value.trim().parse::<f64>() {
+        Ok(v) if v.is_finite() => v,
+        _ => return Err(format!("{} cannot be parsed as a valid value", value))
+    };
+
+    let scale = (n as f64).exp2();
+    let min = -((bits - 1) as f64).exp2();
+    let max = ((bits - 1) as f64).exp2() - 1.0;
+    let raw = (v * scale).round();
+    let mut notes = vec![];
+    let raw = if raw < min || raw > max {
+        let saturated = raw.clamp(min, max);
+        notes.push(format!("{} is out of the range of {} ({}..{}), it saturates to {}", value, format, min / scale, max / scale, saturated / scale));
+        saturated
+    } else {
+        if raw / scale != v {
+            notes.push(format!("{} can't be exact in {}, it is stored as {} (error {:e})", value, format, raw / scale, raw / scale - v));
+        }
+        raw
+    };
+    Ok((raw as i64, bits, notes))
+}
+
+/// Range of the integer data types a constant of .EQU can have
+pub fn int_range(data_type: &str) -> Option<(i64, i64)> {
+    match data_type {
+        "byte" => Some((0, u8::MAX as i64)),
+        "sbyte" => Some((i8::MIN as i64, i8::MAX as i64)),
+        "word" => Some((0, u16::MAX as i64)),
+        "sword" => Some((i16::MIN as i64, i16::MAX as i64)),
+        "dword" => Some((0, u32::MAX as i64)),
+        "sdword" => Some((i32::MIN as i64, i32::MAX as i64)),
+        "qword" => Some((0, i64::MAX)),
+        _ => None
+    }
+}
+
+/// Sign, radix and digits of a number with an optional "-", in decimal, with
+/// the H, O and B marks after it (such as 7FFFH) or the hex, oct and bin marks
+/// before it (such as hex7FFF). Both the instructions and the dot instructions
+/// take numbers in this way, None when `s` isn't one
+pub fn split_number(s: &str) -> Option<(bool, u32, &str)> {
+    let (negative, s) = match s.strip_prefix("-") {
+        Some(s) => (true, s),
+        None => (false, s)
+    };
+    let (radix, digits) = if let Some(n) = s.strip_prefix("hex") {
+        (16, n)
+    } else if let Some(n) = s.strip_prefix("oct") {
+        (8, n)
+    } else if let Some(n) = s.strip_prefix("bin") {
+        (2, n)
+    } else if !s.starts_with(|c: char| c.is_ascii_digit()) {
+        return None;
+    } else if let Some(n) = s.strip_suffix("H") {
+        (16, n)
+    } else if let Some(n) = s.strip_suffix("O") {
+        (8, n)
+    } else if let Some(n) = s.strip_suffix("B") {
+        (2, n)
+    } else {
+        (10, s)
+    };
+    // from_str_radix would take a second sign
+    if digits.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        Some((negative, radix, digits))
+    } else {
+        None
+    }
+}
+
+/// Value of a number as split_number takes it
+pub fn parse_number(s: &str) -> Option<i64> {
+    let (negative, radix, digits) = split_number(s)?;
+    let value = i64::from_str_radix(digits, radix).ok()?;
+    if negative {value.checked_neg()} else {Some(value)}
+}
+
+/// Value of an expression of numbers (as parse_number takes them), names of
+/// constants which `lookup` knows (a name followed by a name in parentheses,
+/// such as SIZEOF(BUF), is looked up as a whole), parentheses and the operators
+/// "|", "&", "<<", ">>", "+", "-", "*", "/", "%" and unary "-", lowest first
+pub fn eval_expr(expr: &str, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let mut tokens = vec![];
+    let chars = expr.chars().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(chars[start..i].iter().collect::<String>());
+        } else if (c == '<' || c == '>') && chars.get(i + 1) == Some(&c) {
+            tokens.push(format!("{}{}", c, c));
+            i += 2;
+        } else if "+-*/%&|()".contains(c) {
+            tokens.push(c.to_string());
+            i += 1;
+        } else {
+            return Err(format!("Unknown character {:?} in {}", c, expr));
+        }
+    }
+
+    let mut pos = 0;
+    let value = eval_binary(&tokens, &mut pos, 0, lookup)?;
+    match tokens.get(pos) {
+        None => Ok(value),
+        Some(t) => Err(format!("Unexpected {} in {}", t, expr))
+    }
+}
+
+// operators of each precedence level, lowest first
+const EXPR_LEVELS: [&[&str]; 5] = [&["|"], &["&"], &["<<", ">>"], &["+", "-"], &["*", "/", "%"]];
+
+fn eval_binary(tokens: &[String], pos: &mut usize, level: usize, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    if level == EXPR_LEVELS.len() {
+        return eval_unary(tokens, pos, lookup);
+    }
+    let mut value = eval_binary(tokens, pos, level + 1, lookup)?;
+    while let Some(op) = tokens.get(*pos).filter(|t| EXPR_LEVELS[level].contains(&t.as_str())) {
+        *pos += 1;
+        let rhs = eval_binary(tokens, pos, level + 1, lookup)?;
+        let r = match op.as_str() {
+            "|" => Some(value | rhs),
+            "&" => Some(value & rhs),
+            "<<" => u32::try_from(rhs).ok().and_then(|r| value.checked_shl(r)),
+            ">>" => u32::try_from(rhs).ok().and_then(|r| value.checked_shr(r)),
+            "+" => value.checked_add(rhs),
+            "-" => value.checked_sub(rhs),
+            "*" => value.checked_mul(rhs),
+            "/" if rhs == 0 => return Err(String::from("Division by zero")),
+            "/" => value.checked_div(rhs),
+            "%" if rhs == 0 => return Err(String::from("Division by zero")),
+            _ => value.checked_rem(rhs)
+        };
+        value = match r {
+            Some(v) => v,
+            None => return Err(format!("{} {} {} overflows", value, op, rhs))
+        };
+    }
+    Ok(value)
+}
+
+fn eval_unary(tokens: &[String], pos: &mut usize, lookup: &dyn Fn(&str) -> Option<i64>) -> Result<i64, String> {
+    let token = match tokens.get(*pos) {
+        Some(t) => t.as_str(),
+        None => return Err(String::from("The expression ends too early"))
+    };
+    *pos += 1;
+    match token {
+        "-" => match eval_unary(tokens, pos, lookup)?.checked_neg() {
+            Some(v) => Ok(v),
+            None => Err(String::from("Negation overflows"))
+        },
+        "(" => {
+            let value = eval_binary(tokens, pos, 0, lookup)?;
+            if tokens.get(*pos).map(|t| t.as_str()) != Some(")") {
+                return Err(String::from("Missing )"));
+            }
+            *pos += 1;
+            Ok(value)
+        },
+        t if t.starts_with(|c: char| c.is_ascii_digit()) || parse_number(t).is_some() => {
+            parse_number(t).ok_or(format!("{} cannot be parsed as a valid value", t))
+        },
+        t if t.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') => {
+            let call = match (tokens.get(*pos), tokens.get(*pos + 1), tokens.get(*pos + 2)) {
+                (Some(open), Some(arg), Some(close)) if open == "(" && close == ")" => Some(format!("{}({})", t, arg)),
+                _ => None
+            };
+            if let Some(v) = call.as_deref().and_then(lookup) {
+                *pos += 3;
+                return Ok(v);
+            }
+            match lookup(t) {
+                Some(v) => Ok(v),
+                None => Err(format!("Unknown constant {}", t))
+            }
+        },
+        t => Err(format!("Unexpected {}", t))
+    }
+}
+
+// ("q1.15", "0.707") of a fixed-point literal "q1.15(0.707)"
+fn split_fixed(arg: &str) -> Option<(&str, &str)> {
+    let (format, value) = arg.strip_suffix(")")?.split_once("(")?;
+    if format.starts_with("q") {Some((format, value))} else {None}
+}
+
+// ", did you mean %X?" for the register nearest to a misspelled one
+fn suggest_register(arg: &str) -> String {
+    let name = arg.trim_start_matches("[").trim_end_matches("]").trim_start_matches("%");
+    let nearest = REGISTERS.iter().chain(PART_REGISTERS[4..].iter())
+        .map(|r| (edit_distance(name, r), r))
+        .min_by_key(|(d, _)| *d);
+    match nearest {
+        Some((d, r)) if d <= 2 => format!(", did you mean %{}?", r),
+        _ => String::new()
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+    for (i, ca) in a.chars().enumerate() {
+        let mut last = row[0];
+        row[0] = i + 1;
+        for j in 0..b.len() {
+            let next = (row[j + 1] + 1).min(row[j] + 1).min(last + (ca != b[j]) as usize);
+            last = row[j + 1];
+            row[j + 1] = next;
+        }
+    }
+    row[b.len()]
+}
+
+// the code and the data end below 8000H, where addresses end
+const MEMORY_WORDS: usize = REGISTER_FLAG as usize;
+// words of the stack segment kept in the image
+const STACK_IMAGE_WORDS: usize = 32;
+
+// byte orders and widths of the words of the image
+pub const ENDIANS: [&str; 2] = ["little", "big"];
+pub const WORD_SIZES: [u32; 2] = [16, 32];
+
+/// The lines of a source which are not blank, without their comments, and
+/// their line numbers, which count from the first line of the trimmed source
+pub fn source_lines(asm: &str) -> impl Iterator<Item = (usize, &str)> {
+    asm.trim().lines().enumerate()
+        .map(|(i, line)| (i + 1, line.split(";").next().unwrap_or("").trim()))
+        .filter(|(_, line)| !line.is_empty())
+}
+
+/// The op and the arguments of a line of code, "OP a, b" may have blanks
+/// after the commas
+pub fn split_code(line: &str) -> (&str, Option<Vec<&str>>) {
+    match line.split_once(char::is_whitespace) {
+        Some((op, args)) => (op, Some(args.split(",").map(|a| a.trim()).collect())),
+        None => (line, None)
+    }
+}
+
+/// Stop when `endian` or `word_size` is not an image format of the assembler
+pub fn check_image_format(endian: &str, word_size: u32) {
+    if !ENDIANS.contains(&endian) {
+        panic!("[ERROR] The endian should be little or big");
+    }
+    if !WORD_SIZES.contains(&word_size) {
+        panic!("[ERROR] The word size should be 16 or 32");
+    }
+}
+
+/// The 16-bit words of an image of `word_size`-bit words in `endian` byte
+/// order, every 32-bit word holds one 16-bit word in its low half
+pub fn image_words(image: &[u8], endian: &str, word_size: u32) -> Vec<u16> {
+    image.chunks(word_size as usize / 8).map(|w| {
+        let v = if endian == "big" {
+            w.iter().fold(0u32, |v, b| v << 8 | *b as u32)
+        } else {
+            w.iter().rev().fold(0u32, |v, b| v << 8 | *b as u32)
+        };
+        v as u16
+    }).collect()
+}
+
+// the names .SET takes for the start of each segment, and the setting item of
+// the dot instruction processor which keeps it
+const SEGMENT_SETTINGS: [([&str; 3], &str); 3] = [
+    (["CODE", "CODESEGMENT", "S_CODE_SA"], "S_CODE_SA"),
+    (["DATA", "DATASEGMENT", "S_DATA_SA"], "S_DATA_SA"),
+    (["STACK", "STACKSEGMENT", "S_STACK_SA"], "S_STACK_SA")
+];
+
+// dot instructions whose data the dot instruction processor builds
+const DATA_DIRECTIVES: [&str; 4] = [".VAR", ".ARR", ".STR", ".PSTR"];
+
+// extra outputs which `--emit` can ask for
+pub const EMIT_KINDS: [&str; 2] = ["cfg", "stack"];
 
 pub struct Assembler {
-    toml: Node,
+    isa: Isa,
+    instructions_file_path: String,
     code_start_address: u16,
+    // address after the last instruction, set by assemble
+    code_end_address: u16,
     stack_start_address: u16,
     data_start_address: u16,
-    mode: String
+    mode: String,
+    // part of the lines without a .PART, set by ".SET DEFAULT_INIT PART_X"
+    default_part: char,
+    // line number -> part chosen by .PART or DEFAULT_INIT
+    line_parts: HashMap<usize, char>,
+    emit: Vec<String>,
+    // byte order and width in bits of every word of the image
+    endian: String,
+    word_size: u32,
+    // name -> value of the constants of .EQU
+    equ_table: HashMap<String, i64>,
+    // the data of .VAR, .ARR, .STR and .PSTR with its size symbols, and the
+    // field offsets of .STRUCT
+    directives: DotInstrctionsProcessor
 }
 
 impl Assembler {
     pub fn new(instructions_file_path: &str) -> Assembler {
-        let mut instructions_file = File::open(instructions_file_path).unwrap();
-        let mut toml = String::new();
-        instructions_file.read_to_string(&mut toml).unwrap();
-
-        let result = parse(toml.as_str());
-        let result = result.into_dom();
-
         Assembler {
-            toml: result,
+            isa: Isa::load(instructions_file_path),
+            instructions_file_path: String::from(instructions_file_path),
             code_start_address: 0x0,
+            code_end_address: 0x0,
             data_start_address: 0x1000,
             stack_start_address: 0x2000,
-            mode: String::new()
+            mode: String::new(),
+            default_part: 'A',
+            line_parts: HashMap::new(),
+            emit: vec![],
+            endian: String::from("big"),
+            word_size: 16,
+            equ_table: HashMap::new(),
+            directives: DotInstrctionsProcessor::new(vec![])
         }
     }
 
     pub fn set(&mut self, csa: Option<u16>, dsa: Option<u16>, ssa: Option<u16>, mode: String) {
-        self.code_start_address = match csa {
-            None => 0x0,
-            Some(addr) => addr
-        };
-        self.data_start_address = match csa {
-            None => 0x1000,
-            Some(addr) => addr
-        };
-        self.stack_start_address = match csa {
-            None => 0x2000,
-            Some(addr) => addr
-        };
+        self.code_start_address = csa.unwrap_or(0x0);
+        self.data_start_address = dsa.unwrap_or(0x1000);
+        self.stack_start_address = ssa.unwrap_or(0x2000);
         self.mode = mode;
+        for (name, start) in [("code", self.code_start_address), ("data", self.data_start_address), ("stack", self.stack_start_address)] {
+            if start & REGISTER_FLAG != 0 {
+                panic!("[ERROR] The start of the {} segment {:04X} is {}", name, start, ADDRESS_RANGE_ERROR);
+            }
+        }
+    }
+
+    /// Byte order ("little" or "big") and width (16 or 32 bits) of the words of
+    /// the image, ".SET ENDIAN" and ".SET WORD_SIZE" in the source win
+    pub fn set_image_format(&mut self, endian: &str, word_size: u32) {
+        check_image_format(endian, word_size);
+        self.endian = String::from(endian);
+        self.word_size = word_size;
+    }
+
+    /// The 16-bit words of an image written in the image format of this assembler
+    pub fn image_words(&self, image: &[u8]) -> Vec<u16> {
+        image_words(image, &self.endian, self.word_size)
+    }
+
+    fn word_bytes(&self, word: u16) -> Vec<u8> {
+        let size = self.word_size as usize / 8;
+        let bytes = (word as u32).to_le_bytes();
+        if self.endian == "big" {
+            bytes[..size].iter().rev().copied().collect()
+        } else {
+            bytes[..size].to_vec()
+        }
+    }
+
+    /// Ask generate_bcode for extra outputs, one of EMIT_KINDS each
+    pub fn set_emit(&mut self, emit: Vec<String>) {
+        for e in emit.iter() {
+            if !EMIT_KINDS.contains(&e.as_str()) {
+                panic!("[ERROR] Unknown output {}, the outputs are: {}", e, EMIT_KINDS.join(", "));
+            }
+        }
+        self.emit = emit;
     }
 
     pub fn generate_bcode(&mut self, asm_file_path: &str, output_file_path: &str) {
+        let mut asm_file = File::open(asm_file_path).unwrap();
+        let mut asm = String::new();
+        asm_file.read_to_string(&mut asm).unwrap();
+
+        let (output_buf, map, listing) = self.assemble(&asm);
+
+        let mut map_file = File::create(format!("{}.map", output_file_path)).unwrap();
+        map_file.write_all(map.as_bytes()).unwrap();
+        let mut listing_file = File::create(format!("{}.lst", output_file_path)).unwrap();
+        listing_file.write_all(listing.as_bytes()).unwrap();
+        let mut output_file = File::create(output_file_path).unwrap();
+        output_file.write_all(&output_buf).unwrap();
+
+        let words = self.image_words(&output_buf);
+        let mut decoder = Disassembler::new(&self.instructions_file_path);
+        decoder.set_symbols(SymbolMap::parse(&map));
+        let cfg = Cfg::build(&decoder, &words, self.code_start_address, self.code_end_address);
+        if self.emit.iter().any(|e| e == "cfg") {
+            let source = asm.trim().lines().collect::<Vec<_>>();
+            let mut dot_file = File::create(format!("{}.dot", output_file_path)).unwrap();
+            dot_file.write_all(cfg.to_dot(&decoder, Some(&source)).as_bytes()).unwrap();
+        }
+
+        let report = self.check_stack(&cfg, &decoder, &words);
+        if self.emit.iter().any(|e| e == "stack") {
+            let mut stack_file = File::create(format!("{}.stack", output_file_path)).unwrap();
+            stack_file.write_all(report.as_bytes()).unwrap();
+        }
+    }
+
+    // warn when the stack of a part may grow past the stack region, which ends
+    // at the next segment or at 8000H, the end of the addresses. Returns the report of
+    // every entry point and part
+    fn check_stack(&self, cfg: &Cfg, decoder: &Disassembler, words: &[u16]) -> String {
+        let stack_end = [self.code_start_address, self.data_start_address].iter()
+            .filter(|a| **a > self.stack_start_address)
+            .map(|a| *a as usize)
+            .min()
+            .unwrap_or(MEMORY_WORDS);
+        let size = stack_end as i64 - self.stack_start_address as i64;
+        let symbols = decoder.symbols();
+        let line_of = |addr: u16| symbols.lines.get(&addr).map_or(String::from("?"), |l| l.to_string());
+
+        let mut report = format!("stack {:04X}..{:04X} ({} words)\n", self.stack_start_address, stack_end, size);
+        for usage in StackUsage::analyze(cfg, decoder, words, self.code_start_address) {
+            let name = match symbols.labels.get(&usage.entry) {
+                Some(n) => n.clone(),
+                None if usage.entry == self.code_start_address => String::from("(start)"),
+                None => String::new()
+            };
+            match usage.depth {
+                Depth::Words(d) => {
+                    report += &format!("{:04X} {:<16} {} {} words\n", usage.entry, name, usage.part, d);
+                    if usage.entry == self.code_start_address && d > size {
+                        println!("[WARNING] The stack of part {} can grow to {} words, but the stack region {:04X}..{:04X} only holds {} words",
+                            usage.part, d, self.stack_start_address, stack_end, size);
+                    }
+                },
+                Depth::Unbounded(addr) => {
+                    report += &format!("{:04X} {:<16} {} unbounded, line {}\n", usage.entry, name, usage.part, line_of(addr));
+                    if usage.entry == self.code_start_address {
+                        println!("[WARNING] Line{}:The stack of part {} has no bound, the loop or recursive call here keeps pushing",
+                            line_of(addr), usage.part);
+                    }
+                }
+            }
+        }
+        report
+    }
+
+    /// Assemble source text, returns the image, its symbol map and the listing
+    pub fn assemble(&mut self, asm: &str) -> (Vec<u8>, String, String) {
         let mut valid_code = vec![];
         let mut bcode = vec![];
 
@@ -58,25 +541,34 @@ impl Assembler {
         let mut datas = vec![];
         let mut data_table: HashMap<&str, u16> = HashMap::new();
         let mut data_ptr: u16 = 0;
+        let mut part: Option<char> = None;
+        let mut directives = vec![];
+        let mut in_struct = false;
+        let mut equs = vec![];
+        self.line_parts.clear();
+        self.equ_table.clear();
 
-        let mut asm_file = File::open(asm_file_path).unwrap();
-        let mut asm = String::new();
-        asm_file.read_to_string(&mut asm).unwrap();
-
-        let asm = asm.trim().lines();
-        for (line_num, line) in asm.enumerate() {
-            let line_num = line_num + 1;
-            let line = line.split(";").collect::<Vec<_>>()[0];
-            let line = line.trim();
-            if line.eq("") || line.eq("\t") || line.eq("\t") {
-                continue;
+        let source = asm.trim().lines().collect::<Vec<_>>();
+        for (line_num, line) in source_lines(asm) {
+            if in_struct || line.starts_with(".STRUCT") || line.starts_with(".ENDS") {
+                // the layout of a .STRUCT, its fields don't start with "."
+                in_struct = !line.starts_with(".ENDS");
+                directives.push((line_num, String::from(line)));
+            } else if DATA_DIRECTIVES.contains(&line.split_whitespace().next().unwrap_or("")) {
+                directives.push((line_num, String::from(line)));
             } else if line.starts_with(".") {
-                let line = line.split(" ").collect::<Vec<_>>();
-                if line[0] == ".DEFINE" {
-                    if line[1].chars().collect::<Vec<_>>()[0].is_ascii_digit() {
-                        panic!("[Syntex Error] line{}:", line_num);
+                // the arguments may be lined up with any number of blanks
+                let text = line;
+                let line = line.split_whitespace().collect::<Vec<_>>();
+                if line[0] == ".EQU" {
+                    // ".EQU NAME [type] expr", evaluated after the data is built
+                    equs.push((line_num, line));
+                } else if line[0] == ".DEFINE" || line[0] == ".DEF" {
+                    // ".DEF NAME text", the README's name of .DEFINE
+                    if line.len() != 3 || line[1].starts_with(|c: char| c.is_ascii_digit()) {
+                        panic!("[Syntex Error] Line{}:{} needs a name which does not start with a digit and the text it stands for", line_num, line[0]);
                     } else {
-                        define_table.insert(line[1].trim(), line[2].trim());
+                        define_table.insert(line[1], line[2]);
                     }
                 } else if line[0] == ".STRING" {
                     // BUG: Can't find string no named
@@ -84,7 +576,8 @@ impl Assembler {
                     if line[1].chars().collect::<Vec<_>>()[0].is_ascii_digit() {
                         panic!("[Syntex Error] line{}:", line_num);
                     } else {
-                        let string = line[2..line.len()].join(" ");
+                        // the blanks inside the string are kept
+                        let string = text.trim_start_matches(".STRING").trim_start().strip_prefix(line[1]).unwrap_or("").trim();
                         let mut string = String::from(string.trim_start_matches("\"").trim_end_matches("\""));
                         if (string.len() & 1) == 1 {
                             string += &String::from("\0\0");
@@ -109,195 +602,578 @@ impl Assembler {
                     if line[2].starts_with("\"") && line[2].ends_with("\"") {
                         datas.push(line[2].trim_start_matches("\"").trim_end_matches("\"").chars().collect::<Vec<_>>()[0] as u16);
                     } else {
-                        if line[2].ends_with("H") {
-                            datas.push(u16::from_str_radix(line[2].trim_end_matches("H"), 16).unwrap());
-                        } else if line[2].ends_with("O") {
-                            datas.push(u16::from_str_radix(line[2].trim_end_matches("O"), 8).unwrap());
-                        } else if line[2].ends_with("B") {
-                            datas.push(u16::from_str_radix(line[2].trim_end_matches("B"), 2).unwrap());
-                        } else {
-                            datas.push(u16::from_str_radix(line[2], 10).unwrap());
+                        match parse_number(line[2]).and_then(|v| u16::try_from(v).ok()) {
+                            Some(v) => datas.push(v),
+                            None => panic!("[Syntex Error] Line{}:{} cannot be parsed as a valid value", line_num, line[2])
                         }
                     }
                     data_ptr += 1;
-                } else if line[0] == ".SET" {
-                    let mut c = 0;
-                    if line[2].ends_with("H") {
-                        c = u16::from_str_radix(line[2].trim_end_matches("H"), 16).unwrap();
-                    } else if line[2].ends_with("O") {
-                        c = u16::from_str_radix(line[2].trim_end_matches("O"), 8).unwrap();
-                    } else if line[2].ends_with("B") {
-                        c = u16::from_str_radix(line[2].trim_end_matches("B"), 2).unwrap();
-                    } else {
-                        c = u16::from_str_radix(line[2], 10).unwrap();
+                } else if line[0] == ".PART" {
+                    match line.get(1).and_then(|p| p.chars().next()).filter(|p| line[1].len() == 1 && PARTS.contains(p)) {
+                        Some(p) => part = Some(p),
+                        None => panic!("[Syntex Error] Line{}:The part should be one of A, B, C and D.", line_num)
+                    }
+                } else if line[0] == ".SET" && line.get(1) == Some(&"DEFAULT_INIT") {
+                    let value = line.get(2).copied().unwrap_or("");
+                    match value.strip_prefix("PART_").and_then(|p| p.chars().next()).filter(|p| value.len() == 6 && PARTS.contains(p)) {
+                        Some(p) => {
+                            self.default_part = p;
+                            if part.is_none() {
+                                part = Some(p);
+                            }
+                        },
+                        None => panic!("[Syntex Error] Line{}:The default part should be one of PART_A, PART_B, PART_C and PART_D.", line_num)
+                    }
+                } else if line[0] == ".SET" && line.get(1) == Some(&"ENDIAN") {
+                    match line.get(2) {
+                        Some(e) if ENDIANS.contains(e) => self.endian = String::from(*e),
+                        _ => panic!("[Syntex Error] Line{}:The endian should be little or big.", line_num)
+                    }
+                } else if line[0] == ".SET" && line.get(1) == Some(&"WORD_SIZE") {
+                    match line.get(2).and_then(|w| w.parse::<u32>().ok()) {
+                        Some(w) if WORD_SIZES.contains(&w) => self.word_size = w,
+                        _ => panic!("[Syntex Error] Line{}:The word size should be 16 or 32.", line_num)
                     }
-                    match line[1] {
-                        "CODE" => {self.code_start_address = c},
-                        "STACK" => {self.stack_start_address = c},
-                        "DATA" => {self.data_start_address = c},
-                        _ => panic!("[Syntex Error] Line{}:Does not spport this setting item.", line_num)
+                } else if line[0] == ".SET" {
+                    // the start of a segment is a setting of the dot instruction
+                    // processor, such as S_STACK_SA for ".SET STACKSEGMENT hex1000"
+                    let item = match SEGMENT_SETTINGS.iter().find(|(names, _)| line.get(1).is_some_and(|n| names.contains(n))) {
+                        Some((_, item)) => item,
+                        None => panic!("[Syntex Error] Line{}:Does not spport this setting item.", line_num)
+                    };
+                    let value = line.get(2).copied().unwrap_or("");
+                    match parse_number(value) {
+                        Some(c) if (0..REGISTER_FLAG as i64).contains(&c) => directives.push((line_num, format!(".SET {} {}", item, c))),
+                        Some(_) => panic!("[Syntex Error] Line{}:The start of a segment {} is {}", line_num, value, ADDRESS_RANGE_ERROR),
+                        None => panic!("[Syntex Error] Line{}:{} cannot be parsed as a valid value", line_num, value)
                     }
                 }
             } else if line.starts_with(";"){
                 continue;
             } else {
-                let code = line.split(" ").collect::<Vec<_>>();
+                let (op, args) = split_code(line);
+                if let Some(p) = part {
+                    self.line_parts.insert(line_num, p);
+                }
 
-                if code.len() == 0 {
-                    continue;
-                } else if code.len() == 1 {
-                    valid_code.push((code[0], None, line_num));
-                } else if code.len() == 2 {
-                    valid_code.push((code[0], Some(code[1].trim().split(",").collect::<Vec<_>>()), line_num));
+                if args.is_none() {
+                    valid_code.push((op, None, line_num));
+                } else if !args.iter().flatten().any(|a| a.contains(char::is_whitespace)) {
+                    valid_code.push((op, args, line_num));
                 } else {
                     println!("[WARNING] Line{}:Unknown line contnt", line_num);
                 }
             }
         }
 
+        // the data of the dot instruction processor follows the data above,
+        // every piece of it starts at a word
+        let mut dip = DotInstrctionsProcessor::new(directives);
+        // the segments start where set() puts them unless the source moves them
+        let starts = [self.code_start_address, self.data_start_address, self.stack_start_address];
+        let mut result = dip.set("ENDIAN", &self.endian);
+        for ((_, item), start) in SEGMENT_SETTINGS.iter().zip(starts) {
+            result = result.and_then(|_| dip.set(item, &start.to_string()));
+        }
+        if let Err(e) = result.and_then(|_| dip.process()) {
+            for line in e.lines() {
+                println!("[ERROR] {}", line);
+            }
+            panic!("[ERROR] Due to early errors, compiler is stoped");
+        }
+        // checked to be below 8000H by the first pass and by set()
+        let start_of = |item| dip.setting(item).unwrap_or_default() as u16;
+        (self.code_start_address, self.data_start_address, self.stack_start_address) = (start_of("S_CODE_SA"), start_of("S_DATA_SA"), start_of("S_STACK_SA"));
+        let items = dip.items();
+        self.directives = dip;
+        for (name, bytes) in items.iter() {
+            if data_table.contains_key(name.as_str()) || define_table.contains_key(name.as_str()) {
+                panic!("[Syntex Error] {} is already defined", name);
+            }
+            data_table.insert(name.as_str(), data_ptr);
+            for pair in bytes.chunks(2) {
+                let (first, second) = (pair[0] as u16, pair.get(1).copied().unwrap_or(0) as u16);
+                // a value of the data keeps its byte order in the image
+                datas.push(if self.endian == "big" {first << 8 | second} else {second << 8 | first});
+                data_ptr += 1;
+            }
+        }
+
+        // the constants of .EQU, an expression can use the constants before it
+        // and the size symbols of the data
+        for (line_num, line) in equs.iter() {
+            let name = line.get(1).copied().unwrap_or("");
+            if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+                panic!("[Syntex Error] Line{}:The constant needs a name which does not start with a digit", line_num);
+            }
+            if self.equ_table.contains_key(name) || define_table.contains_key(name) || data_table.contains_key(name) || self.directives.defines(name) {
+                panic!("[Syntex Error] Line{}:{} is already defined", line_num, name);
+            }
+            let (data_type, expr) = match line.get(2) {
+                Some(t) if int_range(t).is_some() => (Some(*t), line[3..].join(" ")),
+                _ => (None, line[2..].join(" "))
+            };
+            let value = match eval_expr(&expr, &|n| self.constant(n)) {
+                Ok(v) => v,
+                Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
+            };
+            if let Some((min, max)) = data_type.and_then(int_range) {
+                if value < min || value > max {
+                    panic!("[Syntex Error] Line{}:{} = {} does not fit in {} ({}..{})", line_num, name, value, data_type.unwrap(), min, max);
+                }
+            }
+            self.equ_table.insert(String::from(name), value);
+        }
+
         // insert compile pre operation
         let c_s  = self.stack_start_address.to_string();
+        let (ss, sp) = (format!("%{}SS", self.default_part), format!("%{}SP", self.default_part));
         if self.mode.eq("bin") {
-            valid_code.insert(0, ("LOAD", Some(vec![c_s.as_str(), "%SS"],), 0));
+            valid_code.insert(0, ("LOAD", Some(vec![c_s.as_str(), ss.as_str()],), 0));
+            // the stack grows up from its start, PUSH and POP use %SP
+            valid_code.insert(1, ("LOAD", Some(vec![c_s.as_str(), sp.as_str()],), 0));
         } else if self.mode.eq("lib") {
             // not supported
         } else {
             panic!("[ERROR] Unknown mode")
         }
 
-        // to bcode
-        let mut addr: u16 = self.code_start_address;
-        let mut label_table: HashMap<&str, u16> = HashMap::new();
+        // replace the defines, labels need to be on separate lines
+        let mut code = vec![];
         for (op, args, line_num) in valid_code {
-            let mut new_args = vec![];
-            let args_num = match args {
-                None => 0,
-                Some(args) => {
-                    for arg in args.clone() {
-                        match define_table.get(arg) {
-                            Some(e) => new_args.push(*e),
-                            None => new_args.push(arg)
-                        }
-                    }
-                    args.len()
-                }
+            let args = match args {
+                None => vec![],
+                Some(args) => args.iter().map(|a| match define_table.get(a) {
+                        Some(e) => *e,
+                        None => *a
+                    }).collect::<Vec<_>>()
             };
-
             let op = match define_table.get(op) {
-                Some(s) => s,
+                Some(s) => *s,
                 None => op
             };
+            if op.ends_with(":") && !args.is_empty() {
+                panic!("[Syntex Error] Line{}:Labels need to be on separate lines.", line_num);
+            }
+            // registers of other parts need to be named with their part, such as %B.SP
+            if let Some(part) = self.line_parts.get(&line_num) {
+                for arg in args.iter() {
+                    let name = arg.trim_start_matches("[").trim_end_matches("]").trim_start_matches("%");
+                    match register_code(name).and_then(register_part) {
+                        Some(p) if arg.contains("%") && p != *part => println!("[WARNING] Line{}:%{} is a register of part {}, but the code is for part {}, write %{}.{} to use it on purpose",
+                            line_num, name, p, part, p, &name[1..]),
+                        _ => ()
+                    }
+                }
+            }
+            // fixed-point literals are checked once here, matching them against
+            // the variants converts them again
+            for (format, value) in args.iter().filter_map(|a| split_fixed(a)) {
+                match fixed_point(format, value) {
+                    Ok((_, _, notes)) => for note in notes {
+                        println!("[WARNING] Line{}:{}", line_num, note);
+                    },
+                    Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
+                }
+            }
+            code.push((op, args, line_num));
+        }
+
+        // layout, a short branch whose target is out of its reach is replaced by
+        // its "relax" sequence. That moves the labels after it, so the layout is
+        // repeated until no more branches need to be replaced
+        let mut relaxed = vec![false; code.len()];
+        let mut label_table: HashMap<&str, u16> = HashMap::new();
+        loop {
+            let mut addr: u16 = self.code_start_address;
+            label_table.clear();
+            for (i, (op, args, line_num)) in code.iter().enumerate() {
+                if op.ends_with(":") {
+                    if label_table.insert(op.trim_end_matches(":"), addr).is_some() {
+                        panic!("[Syntex Error] Line{}:Label {} is defined more than once", line_num, op.trim_end_matches(":"));
+                    }
+                    if self.equ_table.contains_key(op.trim_end_matches(":")) {
+                        panic!("[Syntex Error] Line{}:{} is already defined by .EQU", line_num, op.trim_end_matches(":"));
+                    }
+                } else if let Some(pseudo) = self.isa.pseudo(op, args.len()) {
+                    addr += self.sequence_length(&pseudo.expand, *line_num);
+                } else if let Some(inst) = self.isa.instruction(op) {
+                    addr += self.instruction_length(inst, relaxed[i], *line_num);
+                }
+            }
+
+            let mut changed = false;
+            let mut addr: u16 = self.code_start_address;
+            for (i, (op, args, line_num)) in code.iter().enumerate() {
+                if op.ends_with(":") {
+                    continue;
+                }
+                if let Some(pseudo) = self.isa.pseudo(op, args.len()) {
+                    addr += self.sequence_length(&pseudo.expand, *line_num);
+                    continue;
+                }
+                let (inst, variant, values) = self.match_variant(op, args, *line_num, &label_table, &data_table);
+                if !relaxed[i] && !inst.relax.is_empty() && !self.isa.reaches(inst, variant, &values, addr) {
+                    relaxed[i] = true;
+                    changed = true;
+                }
+                addr += self.instruction_length(inst, relaxed[i], *line_num);
+            }
+            if !changed {
+                break;
+            }
+        }
 
+        // to bcode, instructions replaced by a sequence get one listing entry
+        // for each instruction of the sequence
+        let mut addr: u16 = self.code_start_address;
+        let mut line_table: Vec<(usize, u16)> = vec![];
+        let mut listing_entries: Vec<(usize, u16, Vec<u16>, Option<String>)> = vec![];
+        for (i, (op, args, line_num)) in code.iter().enumerate() {
             if op.ends_with(":") {
-                if args_num == 0 {
-                    label_table.insert(op.trim_end_matches(":"), addr);
+                continue;
+            }
+
+            let sequence = if let Some(pseudo) = self.isa.pseudo(op, args.len()) {
+                if pseudo.arg_num != args.len() {
+                    panic!("[Syntex Error] Line{}:{} need {} arguments but give {}", line_num, op, pseudo.arg_num, args.len());
+                }
+                if self.match_kinds(&pseudo.variants, args, *line_num, &label_table, &data_table).is_none() {
+                    panic!("[Syntex Error] Line{}:The {} directive does not support this type of parameter(s)", line_num, op);
+                }
+                Some(self.expand(&pseudo.expand, args, *line_num, addr, &mut label_table, &data_table))
+            } else {
+                let (inst, variant, values) = self.match_variant(op, args, *line_num, &label_table, &data_table);
+                self.check_registers(inst, variant, &values, *line_num);
+                if relaxed[i] {
+                    Some(self.expand(&inst.relax, args, *line_num, addr, &mut label_table, &data_table))
                 } else {
-                    panic!("[Syntex Error] Line{}:Labels need to be on separate lines.", line_num);
+                    match self.isa.encode(inst, variant, &values, addr) {
+                        Ok(w) => {
+                            // the compile pre operation has no source line to show
+                            let text = if *line_num == 0 {Some(format!("{} {}", op, args.join(",")))} else {None};
+                            listing_entries.push((*line_num, addr, w.clone(), text));
+                            None
+                        },
+                        Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
+                    }
                 }
-                addr += 1 + args_num as u16;
-                continue;
+            };
+
+            let start = addr;
+            match sequence {
+                Some(sequence) => for (text, mut words) in sequence {
+                    listing_entries.push((*line_num, addr, words.clone(), Some(text)));
+                    addr += words.len() as u16;
+                    bcode.append(&mut words);
+                },
+                None => {
+                    let mut words = listing_entries.last().unwrap().2.clone();
+                    addr += words.len() as u16;
+                    bcode.append(&mut words);
+                }
+            }
+            // line 0 is the inserted compile pre operation
+            if *line_num != 0 {
+                line_table.push((*line_num, start));
             }
+        }
+        label_table.remove(RELAX_END);
+        self.code_end_address = addr;
 
-            let op_info = match self.toml.try_get(op) {
-                Ok(info) => info,
-                Err(Error::Query(error::QueryError::NotFound)) => panic!("[Syntex Error] Line{}:Undefined instruction {}", line_num, op),
-                Err(e) => panic!("[ERROR] {}", e)
+        // listing, every source line with the address and words of its code
+        let mut listing = String::new();
+        for (line_num, addr, words, text) in listing_entries.iter().filter(|e| e.0 == 0) {
+            let codes = words.iter().map(|w| format!("{:04X}", w)).collect::<Vec<_>>().join(" ");
+            listing += &format!("{:04X}  {:<20}{:>5}  {}\n", addr, codes, line_num, text.clone().unwrap_or_default());
+        }
+        for (i, line) in source.iter().enumerate() {
+            let line_num = i + 1;
+            let entries = listing_entries.iter().filter(|e| e.0 == line_num).collect::<Vec<_>>();
+            let label = match line.trim().strip_suffix(":") {
+                Some(l) => label_table.get(l),
+                None => None
             };
-            let bcode_temp = match op_info.get("bcode").as_integer() {
-                Some(i) => {
-                    match i.value().as_positive() {
-                        Some(b) => b as u16,
-                        None => panic!("[ERROR] Premiter {} info \"bcode\" can't be parsed, please check", op)
-                    }
+            match (entries.first(), label) {
+                (Some((_, addr, words, None)), _) => {
+                    let codes = words.iter().map(|w| format!("{:04X}", w)).collect::<Vec<_>>().join(" ");
+                    listing += &format!("{:04X}  {:<20}{:>5}  {}\n", addr, codes, line_num, line);
+                },
+                (Some((_, addr, _, Some(_))), _) | (None, Some(addr)) => listing += &format!("{:04X}  {:<20}{:>5}  {}\n", addr, "", line_num, line),
+                (None, None) => listing += &format!("{:<26}{:>5}  {}\n", "", line_num, line)
+            }
+            for (_, addr, words, text) in entries.iter() {
+                if let Some(text) = text {
+                    let codes = words.iter().map(|w| format!("{:04X}", w)).collect::<Vec<_>>().join(" ");
+                    listing += &format!("{:04X}  {:<20}{:>5}      + {}\n", addr, codes, "", text);
                 }
-                None => panic!("[ERROR] Premiter {} info dose not have \"bcode\", please check", op)
+            }
+        }
+
+        // symbol map, one "kind name address" entry per line
+        let mut map = format!("segment CODE {:04X}\n", self.code_start_address);
+        map += &format!("segment DATA {:04X}\n", self.data_start_address);
+        map += &format!("segment STACK {:04X}\n", self.stack_start_address);
+        for (name, addr) in label_table.iter() {
+            map += &format!("label {} {:04X}\n", name, addr);
+        }
+        for (name, offset) in data_table.iter() {
+            map += &format!("data {} {:04X}\n", name, offset + self.data_start_address);
+        }
+        for (line_num, addr) in line_table.iter() {
+            map += &format!("line {} {:04X}\n", line_num, addr);
+        }
+        for (name, value) in self.equ_table.iter() {
+            map += &format!("equ {} {}{:X}\n", name, if *value < 0 {"-"} else {""}, value.unsigned_abs());
+        }
+
+        // image, every segment at its address
+        let (layout, errors) = self.layout(bcode.len(), datas.len());
+        listing += "\n";
+        listing += &layout;
+        if !errors.is_empty() {
+            print!("{}", layout);
+            for e in errors.iter() {
+                println!("[ERROR] {}", e);
+            }
+            panic!("[ERROR] The segments overlap, the image can't be built");
+        }
+        let code_start = self.code_start_address as usize;
+        let data_start = self.data_start_address as usize;
+        let image_end = (code_start + bcode.len())
+            .max(data_start + datas.len())
+            .max((self.stack_start_address as usize + STACK_IMAGE_WORDS).min(MEMORY_WORDS));
+        let mut image = vec![0u16; image_end];
+        image[code_start..code_start + bcode.len()].copy_from_slice(&bcode);
+        image[data_start..data_start + datas.len()].copy_from_slice(&datas);
+
+        let mut output_buf = vec![];
+        for i in image {
+            output_buf.append(&mut self.word_bytes(i));
+        }
+
+        (output_buf, map, listing)
+    }
+
+    // summary of the address range of each segment, and the code or data
+    // which runs into the next segment or past 8000H, the end of the addresses. The stack
+    // has no size of its own, it reaches to the next segment
+    fn layout(&self, code_words: usize, data_words: usize) -> (String, Vec<String>) {
+        let mut segments = [
+            ("CODE", self.code_start_address as usize, Some(code_words)),
+            ("DATA", self.data_start_address as usize, Some(data_words)),
+            ("STACK", self.stack_start_address as usize, None)
+        ];
+        segments.sort_by_key(|(_, start, _)| *start);
+
+        let mut summary = format!("{:<8}{:<8}{:<8}{}\n", "segment", "start", "end", "words");
+        let mut errors = vec![];
+        for (i, (name, start, words)) in segments.iter().enumerate() {
+            let (next_name, next_start) = match segments.get(i + 1) {
+                Some((n, s, _)) => (format!("the start of the {} segment", n), *s),
+                None => (String::from("the end of the addresses"), MEMORY_WORDS)
+            };
+            let end = match words {
+                Some(w) => start + w,
+                None => next_start
             };
+            summary += &format!("{:<8}{:<8}{:<8}{}\n", name, format!("{:04X}", start), format!("{:04X}", end), end - start);
+            if end > next_start {
+                errors.push(format!("The {} segment {:04X}..{:04X} runs {} words ({} bytes) past {} at {:04X}",
+                    name, start, end, end - next_start, (end - next_start) * 2, next_name, next_start));
+            }
+        }
+        (summary, errors)
+    }
 
-            let request_arg_num = match op_info.get("arg_num").as_integer() {
-                Some(i) => {
-                    match i.value().as_positive() {
-                        Some(b) => b,
-                        None => panic!("[ERROR] Premiter {} info \"arg_num\" can't be parsed, please check", op)
-                    }
+    // the variant of an instruction which takes these arguments, and their values
+    fn match_variant(&self, op: &str, args: &[&str], line_num: usize, label_table: &HashMap<&str, u16>, data_table: &HashMap<&str, u16>) -> (&InstructionDef, &Variant, Vec<i64>) {
+        let inst = match self.isa.instruction(op) {
+            Some(i) => i,
+            None => panic!("[Syntex Error] Line{}:Undefined instruction {}", line_num, op)
+        };
+
+        if inst.arg_num != args.len() {
+            panic!("[Syntex Error] Line{}:{} need {} arguments but give {}", line_num, op, inst.arg_num, args.len());
+        }
+
+        match self.match_kinds(&inst.variants, args, line_num, label_table, data_table) {
+            Some((variant, values)) => (inst, variant, values),
+            None => panic!("[Syntex Error] Line{}:The {} directive does not support this type of parameter(s)", line_num, op)
+        }
+    }
+
+    fn match_kinds<'v>(&self, variants: &'v [Variant], args: &[&str], line_num: usize, label_table: &HashMap<&str, u16>, data_table: &HashMap<&str, u16>) -> Option<(&'v Variant, Vec<i64>)> {
+        for variant in variants.iter() {
+            let mut equal = true;
+            let mut temp_args = vec![];
+
+            for (p, a) in args.iter().enumerate() {
+                match self.arg_kind_eq(a, &variant.kinds[p], line_num, label_table, data_table) {
+                    Ok((e, b)) => {equal &= e; temp_args.push(b)},
+                    Err(e) if e == "Undefined register" => panic!("[Syntex Error] Line{}:{} {}{}", line_num, e, a, suggest_register(a)),
+                    Err(e) if e == ADDRESS_RANGE_ERROR => panic!("[Syntex Error] Line{}:{} is {}", line_num, a, e),
+                    Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
                 }
-                None => panic!("[ERROR] Premiter {} info dose not have \"arg_num\", please check", op)
+            }
+            if equal {
+                return Some((variant, temp_args));
+            }
+        }
+        None
+    }
+
+    // registers out of the class of their argument are errors, and writing
+    // %ZERO is allowed but most likely a mistake
+    fn check_registers(&self, inst: &InstructionDef, variant: &Variant, values: &[i64], line_num: usize) {
+        for (i, value) in values.iter().enumerate() {
+            if variant.kinds[i] != "regs" {
+                continue;
+            }
+            let name = match register_name(*value as u16) {
+                Some(n) => n,
+                None => continue
             };
+            let class = inst.classes.get(i).map(|c| c.as_str()).unwrap_or("all");
+            let writes = inst.writes.contains(&i);
+            if !self.isa.in_class(class, name) {
+                if writes && name == "PC" {
+                    panic!("[Syntex Error] Line{}:{} writes %PC, only jump instructions can change %PC", line_num, inst.name);
+                }
+                panic!("[Syntex Error] Line{}:%{} can't be argument {} of {}, it takes {} registers", line_num, name, i + 1, inst.name, class);
+            }
+            if writes && name == "ZERO" {
+                println!("[WARNING] Line{}:{} writes %ZERO, the result is lost", line_num, inst.name);
+            }
+        }
+    }
 
-            if request_arg_num != args_num as u64 {
-                panic!("[Syntex Error] Line{}:{} need {} arguments but give {}", line_num, op, request_arg_num, args_num);
-            }
-
-            if args_num != 0 {
-                match op_info.get("arg_kinds").as_array() {
-                    Some(a) => {
-                        let a = a.items();
-                        let mut finded = false;
-                        for i in a.get().iter() {
-                            match i.as_array() {
-                                Some(i) => {
-                                    let i = i.items().get();
-                                    let mut equal = true;
-                                    let mut temp_bcode = vec![];
-                                    let rarg = i.iter().map(|x|
-                                            match x.as_str() {
-                                                Some(j) => j.value(),
-                                                None => panic!("[ERROR] Premiter {} info argument(s) in \"arg_kinds\"can't be parsed, please check", op)
-                                            }
-                                        ).collect::<Vec<_>>();
-                                    let rarg_len = rarg.len();
-
-                                    for (p, a) in new_args.iter().enumerate() {
-                                        match self.arg_kind_eq(a, rarg[p], &label_table, &data_table) {
-                                            Ok((e, b)) => {equal &= e; temp_bcode.push(b)},
-                                            Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
-                                        }
-                                    }
-                                    if equal {
-                                        if rarg_len as u64 == request_arg_num + 1 {
-                                            bcode.push(bcode_temp + rarg[rarg_len - 1].parse::<u16>().unwrap());
-                                        } else if rarg_len as u64 != request_arg_num {
-                                            panic!("[ERROR] request arguments number iligal");
-                                        } else {
-                                            bcode.push(bcode_temp);
-                                        }
-                                        bcode.append(&mut temp_bcode);
-                                        finded = true;
-                                        break;
-                                    }
-                                },
-                                None => panic!("[ERROR] Premiter {} info dose not have argument(s) in \"arg_kinds\", please check", op)
-                            }
-                        }
-                        if !finded {
-                            panic!("[Syntex Error] Line{}:The {} directive does not support this type of parameter(s)", line_num, op);
-                        }
-                    },
-                    None => panic!("[ERROR] Premiter {} info dose not have \"arg_kinds\", please check", op)
+    fn instruction_length(&self, inst: &InstructionDef, relaxed: bool, line_num: usize) -> u16 {
+        if relaxed {
+            self.sequence_length(&inst.relax, line_num)
+        } else {
+            self.isa.format(inst).words as u16
+        }
+    }
+
+    fn sequence_length(&self, sequence: &[String], line_num: usize) -> u16 {
+        sequence.iter().map(|line| {
+            let op = line.split(" ").next().unwrap_or("");
+            match self.isa.instruction(op) {
+                Some(i) => self.isa.format(i).words as u16,
+                None => panic!("[Syntex Error] Line{}:Undefined instruction {} in \"{}\"", line_num, op, line)
+            }
+        }).sum()
+    }
+
+    // encode a "relax" or "expand" sequence at `addr`, returns the text and
+    // words of each instruction. "{n}" stands for the n-th argument, "{n.hi}"
+    // and "{n.lo}" for the high and low 16 bits of its value, or for the two
+    // registers of a register pair, "{n.next}" for the address after it, and
+    // the label "{end}" for the address after the sequence
+    fn expand(&self, sequence: &[String], args: &[&str], line_num: usize, addr: u16, label_table: &mut HashMap<&str, u16>, data_table: &HashMap<&str, u16>) -> Vec<(String, Vec<u16>)> {
+        let end = addr + self.sequence_length(sequence, line_num);
+        label_table.insert(RELAX_END, end);
+
+        let mut values = vec![];
+        for arg in args.iter() {
+            let value = ["imdn", "label", "addr"].iter().find_map(|kind| match self.arg_kind_eq(arg, kind, line_num, label_table, data_table) {
+                Ok((true, v)) => Some(v),
+                _ => None
+            });
+            values.push(value);
+        }
+
+        let mut result = vec![];
+        let mut addr = addr;
+        for line in sequence.iter() {
+            // addresses are 16 bits
+            let mut line = line.replace("{end.hi}", "0").replace("{end.lo}", &end.to_string());
+            for (n, arg) in args.iter().enumerate() {
+                let halves = [format!("{{{}.hi}}", n), format!("{{{}.lo}}", n)];
+                if arg.starts_with("%") && halves.iter().any(|h| line.contains(h)) {
+                    let (hi, lo) = self.register_pair(arg, line_num);
+                    line = line.replace(&halves[0], &hi).replace(&halves[1], &lo);
+                }
+                let placeholder = format!("{{{}.next}}", n);
+                if line.contains(&placeholder) {
+                    match values[n] {
+                        Some(v) if (0..0xFFFF).contains(&v) && v & REGISTER_FLAG as i64 == 0 => line = line.replace(&placeholder, &format!("[0{:X}H]", v + 1)),
+                        _ => panic!("[Syntex Error] Line{}:{} is not a fixed address, the word after it is unknown", line_num, arg)
+                    }
+                }
+                for (part, shift) in [("hi", 16), ("lo", 0)] {
+                    let placeholder = format!("{{{}.{}}}", n, part);
+                    if !line.contains(&placeholder) {
+                        continue;
+                    }
+                    match values[n] {
+                        Some(v) if (-(1 << 31)..=0xFFFF_FFFF).contains(&v) => line = line.replace(&placeholder, &((v >> shift) & 0xFFFF).to_string()),
+                        Some(v) => panic!("[Syntex Error] Line{}:{} does not fit in 32 bits", line_num, v),
+                        None => panic!("[Syntex Error] Line{}:{} has no value", line_num, arg)
+                    }
                 }
+                line = line.replace(&format!("{{{}}}", n), arg);
+            }
+            let (op, line_args) = match line.split_once(" ") {
+                Some((op, a)) => (op, a.trim().split(",").collect::<Vec<_>>()),
+                None => (line.as_str(), vec![])
+            };
+
+            let (inst, variant, values) = self.match_variant(op, &line_args, line_num, label_table, data_table);
+            self.check_registers(inst, variant, &values, line_num);
+            match self.isa.encode(inst, variant, &values, addr) {
+                Ok(w) => {
+                    addr += w.len() as u16;
+                    result.push((line.replace(RELAX_END, &format!("0{:X}H", end)), w));
+                },
+                Err(e) => panic!("[Syntex Error] Line{}:{}", line_num, e)
             }
         }
-        let mut zeros = vec![0u16; self.data_start_address as usize - bcode.len()];
-        let mut output_file = File::create(output_file_path).unwrap();
-        let mut output_buf = vec![];
-        bcode.append(&mut zeros);
-        bcode.append(&mut datas);
-        let mut zeros = vec![0u16; self.stack_start_address as usize - datas.len() + 32];
-        bcode.append(&mut zeros);
+        result
+    }
 
-        for i in bcode {
-            output_buf.push((i >> 8) as u8);
-            output_buf.push((i & 0xFF) as u8);
+    // constants of .EQU, size symbols of the data such as SIZEOF(BUF) and the
+    // field offsets of structs such as PACKET.len
+    fn constant(&self, name: &str) -> Option<i64> {
+        match self.equ_table.get(name) {
+            Some(v) => Some(*v),
+            None => self.directives.symbol(name).map(|v| v as i64)
         }
+    }
 
-        output_file.write(&output_buf).unwrap();
+    // the two registers of "%X:%Y", or a register and the one after it in its
+    // part, such as %A2 and %A3
+    fn register_pair(&self, arg: &str, line_num: usize) -> (String, String) {
+        if let Some((hi, lo)) = arg.split_once(":") {
+            return (String::from(hi), String::from(lo));
+        }
+        let code = match self.resolve_register(arg.trim_start_matches("%"), line_num) {
+            Some(c) => c,
+            None => panic!("[Syntex Error] Line{}:Undefined register {}{}", line_num, arg, suggest_register(arg))
+        };
+        match register_name(code + 1) {
+            Some(next) if register_part(code).is_some() && register_part(code) == register_part(code + 1) =>
+                (format!("%{}", register_name(code).unwrap_or_default()), format!("%{}", next)),
+            _ => panic!("[Syntex Error] Line{}:{} is the last register of its part, write the pair as %X:%Y", line_num, arg)
+        }
+    }
+
+    // physical registers, or part-relative ones in the part of the line
+    fn resolve_register(&self, name: &str, line_num: usize) -> Option<u16> {
+        let part = self.line_parts.get(&line_num).copied().unwrap_or(self.default_part);
+        match part_register(name, part) {
+            Some(n) => register_code(&n),
+            None => register_code(name)
+        }
     }
 
-    fn arg_kind_eq(&self, arg: &str, request_kind: &str, label_table: &HashMap<&str, u16>, data_table: &HashMap<&str, u16>) -> Result<(bool, u16), &str> {
+    fn arg_kind_eq(&self, arg: &str, request_kind: &str, line_num: usize, label_table: &HashMap<&str, u16>, data_table: &HashMap<&str, u16>) -> Result<(bool, i64), &str> {
         let mut equal = false;
-        let mut arg_kind = "unknow";
-        let mut bcode: u16 = 0;
+        let arg_kind;
+        let mut bcode: i64;
         let new_arg = match data_table.get(arg.trim_start_matches("$")) {
-            Some(a) => format!("[{}]", a + self.data_start_address),
+            Some(a) => format!("[{}]", *a as i64 + self.data_start_address as i64),
             None => {
                 if arg.starts_with("$") {
                     return Err("Undefined variable");
@@ -308,85 +1184,64 @@ impl Assembler {
         };
 
         let new_arg = new_arg.as_str();
-        if new_arg.starts_with("%") {
+        if let Some((hi, lo)) = new_arg.split_once(":").filter(|_| new_arg.starts_with("%")) {
+            // a register pair "%X:%Y", the high half in %X
+            arg_kind = "pair";
+            bcode = 0;
+            for half in [hi, lo] {
+                bcode = match self.resolve_register(half.trim_start_matches("%"), line_num) {
+                    Some(c) if half.starts_with("%") => bcode << 16 | c as i64,
+                    _ => return Err("Undefined register")
+                };
+            }
+        } else if new_arg.starts_with("%") {
             arg_kind = "regs";
-            bcode = match new_arg.trim_start_matches("%") {
-                "A" => 0b1000_0000_0000_0000,
-                "B" => 0b1000_0000_0000_0001,
-                "C" => 0b1000_0000_0000_0011,
-                "D" => 0b1000_0000_0000_0010,
-                "E" => 0b1000_0000_0000_0110,
-                "F" => 0b1000_0000_0000_0100,
-                "SS" => 0b1000_0000_0000_0101,
-                "SP" => 0b1000_0000_0000_0111,
-                _ => return Err("Undefined register")
+            bcode = match self.resolve_register(new_arg.trim_start_matches("%"), line_num) {
+                Some(c) => c as i64,
+                None => return Err("Undefined register")
             };
         } else if new_arg.starts_with("[") && new_arg.ends_with("]") {
             arg_kind = "addr";
             let new_arg = new_arg.trim_start_matches("[").trim_end_matches("]");
 
-            if new_arg.ends_with("H") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("H"), 16) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
-                };
-            } else if new_arg.ends_with("O") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("O"), 8) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
-                };
-            } else if new_arg.ends_with("B") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("B"), 2) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
-                };
-            } else {
-                if new_arg.starts_with("%") {
-                    bcode = match new_arg.trim_start_matches("%") {
-                        "A" => 0b1000_0000_0000_0000,
-                        "B" => 0b1000_0000_0000_0001,
-                        "C" => 0b1000_0000_0000_0011,
-                        "D" => 0b1000_0000_0000_0010,
-                        "E" => 0b1000_0000_0000_0110,
-                        "F" => 0b1000_0000_0000_0100,
-                        "SS" => 0b1000_0000_0000_0101,
-                        "SP" => 0b1000_0000_0000_0111,
-                        _ => return Err("Undefined register")
-                    };
-                } else {
-                    bcode = match new_arg.parse::<u16>() {
-                        Ok(c) => c,
-                        Err(e) => return Err("Bad base or base mark")
-                    };
-                }
-            }
-        } else if new_arg.chars().collect::<Vec<_>>()[0].is_ascii_digit() {
-            arg_kind = "imdn";
-            if new_arg.ends_with("H") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("H"), 16) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
-                };
-            } else if new_arg.ends_with("O") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("O"), 8) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
-                };
-            } else if new_arg.ends_with("B") {
-                bcode = match u16::from_str_radix(new_arg.trim_end_matches("B"), 2) {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
+            if let Some(v) = self.constant(new_arg) {
+                bcode = v;
+            } else if new_arg.starts_with("%") {
+                bcode = match self.resolve_register(new_arg.trim_start_matches("%"), line_num) {
+                    Some(c) => c as i64,
+                    None => return Err("Undefined register")
                 };
             } else {
-                bcode = match new_arg.parse::<u16>() {
-                    Ok(c) => c,
-                    Err(e) => return Err("Bad base or base mark")
+                bcode = match parse_number(new_arg) {
+                    Some(c) => c,
+                    None => return Err("Bad base or base mark")
                 };
             }
+            if !new_arg.starts_with("%") && !(0..REGISTER_FLAG as i64).contains(&bcode) {
+                return Err(ADDRESS_RANGE_ERROR);
+            }
+        } else if new_arg.trim_start_matches("-").starts_with(|c: char| c.is_ascii_digit()) || parse_number(new_arg).is_some() {
+            // a leading "-" gives a negative number in any base, such as -10H or -hex10
+            arg_kind = "imdn";
+            bcode = match parse_number(new_arg) {
+                Some(c) => c,
+                None => return Err("Bad base or base mark")
+            };
+        } else if let Some(v) = self.constant(new_arg) {
+            arg_kind = "imdn";
+            bcode = v;
+        } else if let Some((format, value)) = split_fixed(new_arg) {
+            arg_kind = "imdn";
+            bcode = match fixed_point(format, value) {
+                // the field takes the bits of the two's complement
+                Ok((raw, bits, _)) if bits < 64 => raw & ((1 << bits) - 1),
+                Ok((raw, _, _)) => raw,
+                Err(_) => return Err("Bad fixed-point literal")
+            };
         } else {
             arg_kind = "label";
             bcode = match label_table.get(new_arg) {
-                Some(b) => b.to_owned(),
+                Some(b) => *b as i64,
                 None => return Err("Unknown string")
             };
         }
@@ -401,4 +1256,269 @@ impl Assembler {
             Ok((false, 0))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the words of `asm` assembled with the description of the repository
+    fn assemble(asm: &str) -> Vec<u16> {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, _, _) = assembler.assemble(asm);
+        assembler.image_words(&image)
+    }
+
+    #[test]
+    #[should_panic(expected = "Line1:[9000H] is out of the addresses 0..7FFFH")]
+    fn addresses_stay_below_the_register_flag() {
+        assemble("MOV %A1,[9000H]\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Line1:The start of a segment 9000H is out of the addresses")]
+    fn segments_stay_below_the_register_flag() {
+        assemble(".SET DATA 9000H\n.DATA X 1\nMOV $X,%A1\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "The segments overlap")]
+    fn code_stays_below_the_end_of_the_addresses() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(Some(0x7FF0), Some(0x1000), Some(0x2000), String::from("bin"));
+        assembler.assemble(&"NOP\n".repeat(11));
+    }
+
+    #[test]
+    fn layout_reports_the_overlap() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(Some(0x7FF0), Some(0x1000), Some(0x2000), String::from("bin"));
+        let (_, errors) = assembler.layout(0x11, 4);
+        assert_eq!(errors, ["The CODE segment 7FF0..8001 runs 1 words (2 bytes) past the end of the addresses at 8000"]);
+        let (_, errors) = assembler.layout(0x10, 0x1001);
+        assert_eq!(errors, ["The DATA segment 1000..2001 runs 1 words (2 bytes) past the start of the STACK segment at 2000"]);
+    }
+
+    #[test]
+    fn numbers_take_both_marks() {
+        let words = assemble(".ARR sword C -10H,-hex10,7FH\nLOAD hex10,%A1\nLOAD 10H,%A2\n");
+        assert_eq!(words[6..12], [0x0004, 0x10, 0x8001, 0x0004, 0x10, 0x8002]);
+        assert_eq!(words[0x1000..0x1003], [0xFFF0, 0xFFF0, 0x007F]);
+    }
+
+    #[test]
+    fn wide_pseudos_take_register_pairs() {
+        let words = assemble(".DATA RESULT 0\nLOAD32 %A2, 12345678H\nLOAD32 %B1:%C4,-1\nSTORE32 %A2, RESULT\nMOVE %A2, %A3\n");
+        assert_eq!(words[6..12], [0x0004, 0x1234, 0x8002, 0x0004, 0x5678, 0x8003]);
+        assert_eq!(words[12..18], [0x0004, 0xFFFF, 0x800B, 0x0004, 0xFFFF, 0x8018]);
+        assert_eq!(words[18..24], [0x0003, 0x8002, 0x1000, 0x0003, 0x8003, 0x1001]);
+        assert_eq!(words[24..27], [0x0002, 0x8002, 0x8003]);
+    }
+
+    #[test]
+    fn pseudos_expand_with_their_end_label() {
+        let words = assemble(".DATA RESULT 0\nL:\nJEQ %A1,%A2,L\nSTORE32 %A1,RESULT\n");
+        // %R3 is %AR3, BNZ skips the JMP to the {end} of the sequence
+        assert_eq!(words[6..12], [0x0002, 0x8001, 0x8007, 0x0101, 0x8002, 0x8007]);
+        assert_eq!(words[12..16], [0x0502, 0x8007, 0x0200, 6]);
+        // {1.next} is the word after RESULT
+        assert_eq!(words[16..22], [0x0003, 0x8001, 0x1000, 0x0003, 0x8002, 0x1001]);
+    }
+
+    #[test]
+    fn pseudos_take_the_names_of_instructions() {
+        let words = assemble("ADD %A2, %A3, %AR1\nSUB %A1, 1, %A1\nEQ %A1, 0, %A4\nOJMP %A4, DONE\nADD %A1, %A2\nDONE:\nINT 0\n");
+        assert_eq!(words[6..15], [0x0002, 0x8002, 0x8007, 0x0100, 0x8003, 0x8007, 0x0002, 0x8007, 0x8005]);
+        assert_eq!(words[15..24], [0x0004, 1, 0x8007, 0x0002, 0x8001, 0x8001, 0x0101, 0x8007, 0x8001]);
+        assert_eq!(words[24..38], [0x0004, 0, 0x8007, 0x0101, 0x8001, 0x8007, 0x0004, 0, 0x8004, 0x0503, 0x8007, 0x0004, 1, 0x8004]);
+        assert_eq!(words[38..42], [0x0402, 0x8004, 0x0200, 45]);
+        // with two arguments ADD is the instruction
+        assert_eq!(words[42..45], [0x0100, 0x8001, 0x8002]);
+    }
+
+    #[test]
+    fn parts_take_any_blanks() {
+        let words = assemble(".SET   DEFAULT_INIT    PART_B\nLOAD 1,%R1\n.PART\tC\nLOAD 1,%R1\n.PART    D\nLOAD 1,%SP\n");
+        // the stack start goes to the SS and SP of the default part
+        assert_eq!(words[0..6], [0x0004, 0x2000, 0x8012, 0x0004, 0x2000, 0x8013]);
+        assert_eq!(words[6..15], [0x0004, 1, 0x800F, 0x0004, 1, 0x8019, 0x0004, 1, 0x8027]);
+    }
+
+    #[test]
+    #[should_panic(expected = "last register of its part")]
+    fn register_pairs_stay_in_a_part() {
+        assemble("LOAD32 %ADS,1\n");
+    }
+
+    #[test]
+    fn negative_immediates() {
+        let words = assemble("LOAD -1,%A1\nLOAD -hex10,%A2\nLOAD 0FFFFH,%A3\nINT -10H\n");
+        assert_eq!(words[6..9], [0x0004, 0xFFFF, 0x8001]);
+        assert_eq!(words[9..12], [0x0004, 0xFFF0, 0x8002]);
+        assert_eq!(words[12..15], [0x0004, 0xFFFF, 0x8003]);
+        assert_eq!(words[15..17], [0x8000, 0xFFF0]);
+    }
+
+    #[test]
+    fn branches_take_label_offsets() {
+        let words = assemble("L1:\nNOP\nBZ %A1,L2\nBR L1\nBNZ %A2,L1\nL2:\nINT 0\n");
+        // the offsets count from the next instruction, backwards in two's complement
+        assert_eq!(words[6..12], [0x0000, 0x0403, 0x8001, 0x03FC, 0x05FA, 0x8002]);
+    }
+
+    #[test]
+    fn segments_are_settings_of_the_dot_instructions() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble(".SET CODESEGMENT    hex100\n.SET   STACKSEGMENT hex3000\n.SET DATA 2000H\nNOP\n");
+        let words = assembler.image_words(&image);
+        assert_eq!(words[0x100..0x107], [0x0004, 0x3000, 0x8008, 0x0004, 0x3000, 0x8009, 0x0000]);
+        assert!(map.starts_with("segment CODE 0100\nsegment DATA 2000\nsegment STACK 3000\n"));
+    }
+
+    #[test]
+    fn defines_replace_arguments() {
+        let words = assemble(".DEF   COUNTER   %A1\n.DEFINE STEP %A2\nLOAD 5,COUNTER\nSUB STEP,COUNTER\n");
+        assert_eq!(words[6..12], [0x0004, 5, 0x8001, 0x0101, 0x8002, 0x8001]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Line1:.DEF needs a name")]
+    fn defines_need_a_text() {
+        assemble(".DEF COUNTER\n");
+    }
+
+    #[test]
+    fn image_settings_take_any_blanks() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, _, _) = assembler.assemble(".SET ENDIAN    little\n.SET\tWORD_SIZE   32\nNOP\n");
+        // LOAD 2000H,%ASS in three little-endian 32-bit words
+        assert_eq!(image[0..12], [0x04, 0, 0, 0, 0x00, 0x20, 0, 0, 0x08, 0x80, 0, 0]);
+        assert_eq!(assembler.image_words(&image)[6], 0x0000);
+    }
+
+    #[test]
+    fn expressions_report_errors() {
+        let none = |_: &str| None;
+        assert_eq!(eval_expr("1 + 2 * (3 - 1)", &none), Ok(5));
+        assert_eq!(eval_expr("-hex10 + 10H - 1 << 2", &none), Ok(-4));
+        assert_eq!(eval_expr("1 / 0", &none), Err(String::from("Division by zero")));
+        assert_eq!(eval_expr("5 % 0", &none), Err(String::from("Division by zero")));
+        assert_eq!(eval_expr("(1 + 2", &none), Err(String::from("Missing )")));
+        assert_eq!(eval_expr("1 +", &none), Err(String::from("The expression ends too early")));
+        assert_eq!(eval_expr("1 2", &none), Err(String::from("Unexpected 2 in 1 2")));
+        assert_eq!(eval_expr("FOO", &none), Err(String::from("Unknown constant FOO")));
+        assert_eq!(eval_expr("1 $ 2", &none), Err(String::from("Unknown character '$' in 1 $ 2")));
+        assert_eq!(eval_expr("12Z", &none), Err(String::from("12Z cannot be parsed as a valid value")));
+        assert!(eval_expr("7FFFFFFFFFFFFFFFH + 1", &none).unwrap_err().ends_with("overflows"));
+        assert!(eval_expr("1 << 64", &none).unwrap_err().ends_with("overflows"));
+    }
+
+    #[test]
+    fn expressions_look_up_calls_as_a_whole() {
+        let lookup = |n: &str| if n == "SIZEOF(BUF)" {Some(6)} else {None};
+        assert_eq!(eval_expr("SIZEOF(BUF) / 2", &lookup), Ok(3));
+        assert_eq!(eval_expr("SIZEOF(OTHER)", &lookup), Err(String::from("Unknown constant SIZEOF")));
+    }
+
+    #[test]
+    fn fixed_point_reports_errors() {
+        assert_eq!(fixed_point("q1.15", "0.5"), Ok((0x4000, 16, vec![])));
+        assert_eq!(fixed_point("q8.8", "-1.25"), Ok((-320, 16, vec![])));
+        assert!(fixed_point("q0.16", "0.5").unwrap_err().contains("is not a Q format"));
+        assert!(fixed_point("1.15", "0.5").unwrap_err().contains("is not a Q format"));
+        assert!(fixed_point("q1.14", "0.5").unwrap_err().contains("has 15 bits"));
+        assert!(fixed_point("q1.15", "half").unwrap_err().contains("cannot be parsed"));
+        assert!(fixed_point("q1.15", "inf").unwrap_err().contains("cannot be parsed"));
+
+        let (raw, _, notes) = fixed_point("q1.15", "2").unwrap();
+        assert_eq!(raw, 0x7FFF);
+        assert!(notes[0].contains("saturates"));
+        let (_, _, notes) = fixed_point("q1.15", "0.1").unwrap();
+        assert!(notes[0].contains("can't be exact"));
+    }
+
+    #[test]
+    fn misspelled_registers_get_suggestions() {
+        assert_eq!(edit_distance("A1", "A1"), 0);
+        assert_eq!(edit_distance("AR1", "A1"), 1);
+        assert_eq!(edit_distance("", "ASP"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(suggest_register("[%ASPP]"), ", did you mean %ASP?");
+        assert_eq!(suggest_register("%BR5"), ", did you mean %BR1?");
+        assert_eq!(suggest_register("%XYZW"), "");
+    }
+
+    #[test]
+    fn branches_relax_until_every_label_is_reached() {
+        // BR L1 only goes out of reach after BR L2 is relaxed
+        let asm = String::from("BR L1\nBR L2\n") + &"LOAD 1,%A1\n".repeat(42)
+            + "L1:\n" + &"LOAD 1,%A1\n".repeat(67) + "L2:\nBR L1\nBR NEAR\nNOP\nNEAR:\nINT 0\n";
+        let words = assemble(&asm);
+        assert_eq!(words[6..10], [0x0200, 136, 0x0200, 337]);
+        // backwards out of reach, and a short branch over the NOP
+        assert_eq!(words[337..340], [0x0200, 136, 0x0301]);
+    }
+
+    #[test]
+    fn conditional_branches_relax_around_a_jump() {
+        let asm = String::from("BZ %A1,FAR\nBNZ %A2,FAR\n") + &"LOAD 1,%A1\n".repeat(50) + "FAR:\nINT 0\n";
+        let words = assemble(&asm);
+        // the inverted branch skips the JMP, its {end} is the word after the JMP
+        assert_eq!(words[6..10], [0x0502, 0x8001, 0x0200, 164]);
+        assert_eq!(words[10..14], [0x0402, 0x8002, 0x0200, 164]);
+        assert_eq!(words[164..166], [0x8000, 0]);
+    }
+
+    #[test]
+    fn size_symbols_are_immediates() {
+        let words = assemble(".ARR word BUF 1,2,3\nLOAD SIZEOF(BUF),%A1\nLOAD LENGTHOF(BUF),%A2\nLOAD BUF.size,%A3\n");
+        // the two LOADs of the stack start come first
+        assert_eq!(words[6..9], [0x0004, 6, 0x8001]);
+        assert_eq!(words[9..12], [0x0004, 3, 0x8002]);
+        assert_eq!(words[12..15], [0x0004, 6, 0x8003]);
+        assert_eq!(words[0x1000..0x1003], [1, 2, 3]);
+    }
+
+    #[test]
+    fn struct_offsets_are_immediates() {
+        let words = assemble(".STRUCT PACKET\n    word id\n    byte len\n    byte data[8]\n.ENDS\n.VAR PACKET pkt {id=1, len=4}\nLOAD PACKET.len,%A1\nLOAD SIZEOF(PACKET),%A2\n");
+        assert_eq!(words[6..9], [0x0004, 2, 0x8001]);
+        assert_eq!(words[9..12], [0x0004, 11, 0x8002]);
+        // 11 bytes take 6 words
+        assert_eq!(words[0x1000..0x1006], [1, 0x0400, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn constants_use_size_symbols() {
+        let words = assemble(".EQU WORDS (SIZEOF(BUF) + 1) / 2\n.ARR byte BUF 1,2,3\nLOAD WORDS,%A1\n");
+        assert_eq!(words[6..9], [0x0004, 2, 0x8001]);
+    }
+
+    #[test]
+    fn test_maasm_assembles() {
+        // the data where the command line puts it, test.maasm moves the stack
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, Some(0x2000), None, String::from("bin"));
+        let (image, _, _) = assembler.assemble(&std::fs::read_to_string("test.maasm").unwrap());
+        let words = assembler.image_words(&image);
+        // LOAD32 CALC_LOOP_COUNTER, CALC_LOOP_TIMES with the .EQU constant
+        assert_eq!(words[0x12..0x18], [0x0004, 0, 0x8001, 0x0004, 200, 0x8002]);
+        // JMP LOOP
+        assert_eq!(words[0x42..0x44], [0x0200, 0x0018]);
+    }
+
+    #[test]
+    #[should_panic(expected = "BUF is already defined")]
+    fn constants_clash_with_data() {
+        assemble(".ARR byte BUF 1,2,3\n.EQU BUF 4\n");
+    }
+
+    #[test]
+    fn string_lengths_count_characters() {
+        let words = assemble(".STR utf8 S \"h\u{e9}!\"\nLOAD S.len,%A1\nLOAD S.size,%A2\n");
+        assert_eq!(words[6..12], [0x0004, 3, 0x8001, 0x0004, 5, 0x8002]);
+    }
+}
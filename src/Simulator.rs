@@ -0,0 +1,266 @@
+use std::fmt::{Display, Formatter};
+
+use crate::Disassembler::Disassembler;
+use crate::MCAssembler::{REGISTERS, REGISTER_FLAG, image_words, register_code, register_name};
+use crate::SymbolMap::SymbolMap;
+
+pub const MEMORY_SIZE: usize = 0x1_0000;
+pub const ZERO: usize = 0;
+pub const PC: usize = 41;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    // INT n, the simulator has no interrupt controller so the program stops here
+    Interrupt(u16),
+    // the word at this address is not a valid instruction
+    IllegalInstruction(u16),
+    StepLimit
+}
+
+impl Display for StopReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Interrupt(n) => write!(f, "Stopped by interrupt {}", n),
+            StopReason::IllegalInstruction(addr) => write!(f, "Illegal instruction at {:04X}", addr),
+            StopReason::StepLimit => write!(f, "Step limit reached")
+        }
+    }
+}
+
+pub struct Simulator {
+    // indexed by the register numbers of all_register
+    pub registers: [u16; 42],
+    pub memory: Vec<u16>,
+    pub steps: usize,
+    // address of the first instruction, %PC after loading an image
+    start: u16,
+    decoder: Disassembler
+}
+
+impl Simulator {
+    pub fn new(instructions_file_path: &str) -> Simulator {
+        Simulator {
+            registers: [0; 42],
+            memory: vec![0; MEMORY_SIZE],
+            steps: 0,
+            start: 0,
+            decoder: Disassembler::new(instructions_file_path)
+        }
+    }
+
+    /// Load an assembled image at address 0 and start at the start address,
+    /// the image is a sequence of `word_size`-bit words in `endian` byte order
+    /// as written by the assembler
+    pub fn load_image(&mut self, image: &[u8], endian: &str, word_size: u32) {
+        self.load_words(&image_words(image, endian, word_size));
+    }
+
+    /// Load words at address 0, for images in another format of the assembler
+    pub fn load_words(&mut self, words: &[u16]) {
+        for (addr, w) in words.iter().take(MEMORY_SIZE).enumerate() {
+            self.memory[addr] = *w;
+        }
+        self.registers = [0; 42];
+        self.registers[PC] = self.start;
+        self.steps = 0;
+    }
+
+    /// Start the program at `addr`, the start of its CODE segment, instead of 0
+    pub fn set_start(&mut self, addr: u16) {
+        self.start = addr;
+        self.set_pc(addr);
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.registers[PC]
+    }
+
+    pub fn set_pc(&mut self, addr: u16) {
+        self.registers[PC] = addr;
+    }
+
+    pub fn register(&self, name: &str) -> Option<u16> {
+        register_code(name).map(|c| self.registers[(c & !REGISTER_FLAG) as usize])
+    }
+
+    /// Read the symbols of the program, it starts at the CODE segment of the map
+    pub fn load_map(&mut self, map_file_path: &str) {
+        self.set_symbols(SymbolMap::load(map_file_path));
+    }
+
+    pub fn set_symbols(&mut self, symbols: SymbolMap) {
+        if let Some(addr) = symbols.code_start_address {
+            self.set_start(addr);
+        }
+        self.decoder.set_symbols(symbols);
+    }
+
+    pub fn decoder(&self) -> &Disassembler {
+        &self.decoder
+    }
+
+    /// Run until the program stops or `max_steps` instructions have been executed
+    pub fn run(&mut self, max_steps: usize) -> StopReason {
+        for _ in 0..max_steps {
+            if let Some(r) = self.step() {
+                return r;
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    /// Execute one instruction, returning the reason if the program stopped
+    pub fn step(&mut self) -> Option<StopReason> {
+        let pc = self.pc();
+        let end = (pc as usize + self.decoder.isa().max_words()).min(MEMORY_SIZE);
+        let (name, args, len) = match self.decoder.instruction(&self.memory[pc as usize..end]) {
+            Some((name, args, len)) => (String::from(name), args.iter().map(|(k, v)| (String::from(*k), *v)).collect::<Vec<_>>(), len),
+            None => return Some(StopReason::IllegalInstruction(pc))
+        };
+        let kinds = args.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+        let args = args.iter().map(|(_, v)| *v).collect::<Vec<_>>();
+        if kinds.iter().zip(args.iter()).any(|(k, v)| (*k == "regs" || (*k == "addr" && *v & REGISTER_FLAG != 0)) && register_name(*v).is_none()) {
+            return Some(StopReason::IllegalInstruction(pc));
+        }
+
+        self.set_pc(pc.wrapping_add(len as u16));
+        self.steps += 1;
+
+        match (name.as_str(), kinds.as_slice()) {
+            ("NOP", _) => (),
+            ("MOV", ["addr", "regs"]) => {
+                let v = self.memory[self.address(args[0]) as usize];
+                self.write_register(args[1], v);
+            },
+            ("MOV", ["regs", "regs"]) => {
+                let v = self.read_register(args[0]);
+                self.write_register(args[1], v);
+            },
+            ("MOV", ["regs", "addr"]) => {
+                let addr = self.address(args[1]);
+                self.memory[addr as usize] = self.read_register(args[0]);
+            },
+            ("LOAD", _) => self.write_register(args[1], args[0]),
+            // two register operations, the second register is also the result
+            ("ADD", _) | ("SUB", _) | ("AND", _) | ("OR", _) | ("XOR", _) |
+            ("LSL", _) | ("LSR", _) | ("ASL", _) | ("ASR", _) | ("CSL", _) | ("CSR", _) => {
+                let s = self.read_register(args[0]);
+                let t = self.read_register(args[1]);
+                let v = match name.as_str() {
+                    "ADD" => t.wrapping_add(s),
+                    "SUB" => t.wrapping_sub(s),
+                    "AND" => t & s,
+                    "OR" => t | s,
+                    "XOR" => t ^ s,
+                    "LSL" | "ASL" => t.checked_shl(s as u32).unwrap_or(0),
+                    "LSR" => t.checked_shr(s as u32).unwrap_or(0),
+                    "ASR" => (t as i16).checked_shr(s as u32).unwrap_or(if (t as i16) < 0 {-1} else {0}) as u16,
+                    "CSL" => t.rotate_left(s as u32 % 16),
+                    _ => t.rotate_right(s as u32 % 16)
+                };
+                self.write_register(args[1], v);
+            },
+            // single register operations, the reduction ones leave 0 or 1
+            ("NOT", _) | ("RAND", _) | ("ROR", _) | ("RXOR", _) | ("INC", _) | ("DEC", _) => {
+                let t = self.read_register(args[0]);
+                let v = match name.as_str() {
+                    "NOT" => !t,
+                    "RAND" => (t == u16::MAX) as u16,
+                    "ROR" => (t != 0) as u16,
+                    "RXOR" => (t.count_ones() & 1) as u16,
+                    "INC" => t.wrapping_add(1),
+                    _ => t.wrapping_sub(1)
+                };
+                self.write_register(args[0], v);
+            },
+            ("JMP", ["addr"]) => {
+                let addr = self.address(args[0]);
+                self.set_pc(addr);
+            },
+            ("JMP", _) => self.set_pc(args[0]),
+            // offsets are sign-extended and relative to the next instruction
+            ("BR", _) => self.set_pc(self.pc().wrapping_add(args[0])),
+            ("BZ", _) | ("BNZ", _) => {
+                let zero = self.read_register(args[0]) == 0;
+                if zero == (name == "BZ") {
+                    self.set_pc(self.pc().wrapping_add(args[1]));
+                }
+            },
+            ("INT", _) => return Some(StopReason::Interrupt(args[0])),
+            _ => {
+                self.set_pc(pc);
+                self.steps -= 1;
+                return Some(StopReason::IllegalInstruction(pc));
+            }
+        }
+
+        None
+    }
+
+    pub fn dump_registers(&self) -> String {
+        let mut dump = String::new();
+        for (i, name) in REGISTERS.iter().enumerate() {
+            dump += &format!("{:>4} = {:04X}", name, self.registers[i]);
+            dump += if i % 6 == 5 || i == REGISTERS.len() - 1 {"\n"} else {"    "};
+        }
+        dump
+    }
+
+    fn read_register(&self, code: u16) -> u16 {
+        self.registers[(code & !REGISTER_FLAG) as usize]
+    }
+
+    fn write_register(&mut self, code: u16, value: u16) {
+        let r = (code & !REGISTER_FLAG) as usize;
+        // %ZERO always reads as zero
+        if r != ZERO {
+            self.registers[r] = value;
+        }
+    }
+
+    // address operands are either an absolute address or a register holding one
+    fn address(&self, arg: u16) -> u16 {
+        if arg & REGISTER_FLAG != 0 {
+            self.read_register(arg)
+        } else {
+            arg
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MCAssembler::Assembler;
+
+    #[test]
+    fn programs_run_from_the_code_segment() {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble(".SET CODE 100H\n.DATA RESULT 0\nLOAD 5,%A1\nLOAD 7,%A2\nADD %A1,%A2\nMOV %A2,RESULT\nMOV RESULT,%B1\nJMP END\nINT 1\nEND:\nINT 3\n");
+
+        let mut simulator = Simulator::new("docs/instructions.toml");
+        simulator.set_symbols(SymbolMap::parse(&map));
+        simulator.load_image(&image, "big", 16);
+        assert_eq!(simulator.pc(), 0x100);
+        assert!(matches!(simulator.run(100), StopReason::Interrupt(3)));
+        // the two LOADs of the stack start, seven instructions and INT 3
+        assert_eq!(simulator.steps, 9);
+        assert_eq!(simulator.register("A1"), Some(5));
+        assert_eq!(simulator.register("A2"), Some(12));
+        assert_eq!(simulator.register("B1"), Some(12));
+        assert_eq!(simulator.register("ASP"), Some(0x2000));
+        assert_eq!(simulator.memory[0x1000], 12);
+    }
+
+    #[test]
+    fn illegal_instructions_stop_the_program() {
+        let mut simulator = Simulator::new("docs/instructions.toml");
+        // MOV with a register which doesn't exist
+        simulator.load_words(&[0x0002, 0x80FF, 0x8001]);
+        assert!(matches!(simulator.run(10), StopReason::IllegalInstruction(0)));
+        assert_eq!(simulator.steps, 0);
+        simulator.load_words(&[0x0000, 0x0000]);
+        assert!(matches!(simulator.run(2), StopReason::StepLimit));
+    }
+}
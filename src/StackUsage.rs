@@ -0,0 +1,284 @@
+use std::collections::HashMap;
+
+use crate::Cfg::{BasicBlock, Cfg, Successor};
+use crate::Disassembler::Disassembler;
+use crate::MCAssembler::{register_code, PARTS};
+
+pub enum Depth {
+    Words(i64),
+    // a loop or a recursive call at this address keeps pushing
+    Unbounded(u16)
+}
+
+/// Worst-case stack depth of one part from one entry point
+pub struct Usage {
+    pub entry: u16,
+    pub part: char,
+    pub depth: Depth
+}
+
+/// Follow the pushes and pops of every part through the control-flow graph
+///
+/// The stack grows up, so `INC %xSP` pushes and `DEC %xSP` pops a word of
+/// part x. A block ending in a jump whose return address it loads (what the
+/// CALL pseudo instruction expands to) is a call, the walk goes on after the
+/// call with the depth the callee returns with. Jumps through a register
+/// (RET) return. The entry points are `start` and every called address.
+pub fn analyze(cfg: &Cfg, decoder: &Disassembler, words: &[u16], start: u16) -> Vec<Usage> {
+    let blocks = cfg.blocks.iter().map(|b| (b.start, b)).collect::<HashMap<_, _>>();
+    let mut entries = vec![start];
+    for block in cfg.blocks.iter() {
+        if let Some((callee, _)) = call(block, decoder, words) {
+            entries.push(callee);
+        }
+    }
+    entries.sort();
+    entries.dedup();
+
+    let mut usages = vec![];
+    for part in PARTS {
+        let sp = register_code(&format!("{}SP", part)).unwrap();
+        let mut walker = Walker {
+            blocks: &blocks,
+            decoder,
+            words,
+            sp,
+            functions: HashMap::new(),
+            calling: vec![],
+            walked: HashMap::new()
+        };
+        // parts which never push don't need a stack
+        if !cfg.blocks.iter().any(|b| walker.effect(b) != (0, 0)) {
+            continue;
+        }
+        for entry in entries.iter() {
+            let depth = match walker.function(*entry) {
+                Ok((peak, _)) => Depth::Words(peak),
+                Err(addr) => Depth::Unbounded(addr)
+            };
+            usages.push(Usage {entry: *entry, part, depth});
+        }
+    }
+    usages
+}
+
+// (callee, return address) when the block ends in a call
+fn call(block: &BasicBlock, decoder: &Disassembler, words: &[u16]) -> Option<(u16, u16)> {
+    let last = block.instructions.last()?;
+    let (name, _, len) = decoder.instruction(&words[last.addr as usize..])?;
+    let back = last.addr + len as u16;
+    let callee = match block.successors.first() {
+        Some(Successor::Block(t, "jump")) if name == "JMP" => *t,
+        _ => return None
+    };
+    let loads_back = block.instructions.iter().any(|i| match decoder.instruction(&words[i.addr as usize..]) {
+        Some(("LOAD", args, _)) => args[0] == ("imdn", back),
+        _ => false
+    });
+    if loads_back {Some((callee, back))} else {None}
+}
+
+struct Walker<'a> {
+    blocks: &'a HashMap<u16, &'a BasicBlock>,
+    decoder: &'a Disassembler,
+    words: &'a [u16],
+    sp: u16,
+    // entry -> (peak, depth on return) relative to the entry
+    functions: HashMap<u16, Result<(i64, Option<i64>), u16>>,
+    calling: Vec<u16>,
+    // (block, depth) -> (peak, depth on return), only of walks which didn't
+    // loop back into a block still being walked
+    walked: HashMap<(u16, i64), (i64, Option<i64>)>
+}
+
+impl Walker<'_> {
+    // (highest, last) depth in the block relative to its start
+    fn effect(&self, block: &BasicBlock) -> (i64, i64) {
+        let (mut peak, mut depth) = (0, 0);
+        for inst in block.instructions.iter() {
+            match self.decoder.instruction(&self.words[inst.addr as usize..]) {
+                Some(("INC", args, _)) if args[0].1 == self.sp => depth += 1,
+                Some(("DEC", args, _)) if args[0].1 == self.sp => depth -= 1,
+                _ => ()
+            }
+            peak = peak.max(depth);
+        }
+        (peak, depth)
+    }
+
+    fn function(&mut self, entry: u16) -> Result<(i64, Option<i64>), u16> {
+        if let Some(r) = self.functions.get(&entry) {
+            return *r;
+        }
+        if self.calling.contains(&entry) {
+            return Err(entry);
+        }
+        self.calling.push(entry);
+        let r = self.walk(entry);
+        self.calling.pop();
+        self.functions.insert(entry, r);
+        r
+    }
+
+    // the highest depth from `entry` on and the highest depth it returns with.
+    // The blocks being walked are kept on `path` instead of the call stack of
+    // Rust, so a long chain of blocks doesn't overflow it
+    fn walk(&mut self, entry: u16) -> Result<(i64, Option<i64>), u16> {
+        let mut path: Vec<Frame> = vec![];
+        // block -> its index in `path`
+        let mut on_path = HashMap::new();
+        let mut result = self.enter(entry, 0, &mut path, &mut on_path)?;
+        loop {
+            let frame = match path.last_mut() {
+                Some(f) => f,
+                None => {
+                    let (peak, back, _) = result.unwrap_or_default();
+                    return Ok((peak, back));
+                }
+            };
+            // the walk of a successor is done, add it to the block it came from
+            if let Some((peak, back, loops_to)) = result.take() {
+                frame.peak = frame.peak.max(peak);
+                frame.back = frame.back.max(back);
+                frame.loops_to = match (frame.loops_to, loops_to) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b)
+                };
+            }
+            match frame.todo.pop() {
+                Some((start, depth)) => result = self.enter(start, depth, &mut path, &mut on_path)?,
+                None => {
+                    let frame = path.pop().unwrap();
+                    on_path.remove(&frame.start);
+                    // loops back to this block are done now, loops to earlier ones aren't
+                    let loops_to = frame.loops_to.filter(|i| *i < path.len());
+                    if loops_to.is_none() {
+                        self.walked.insert((frame.start, frame.depth), (frame.peak, frame.back));
+                    }
+                    result = Some((frame.peak, frame.back, loops_to));
+                }
+            }
+        }
+    }
+
+    // start walking the block at `start` entered with `depth`. Returns the
+    // highest depth from it on, the highest depth it returns with and the index
+    // in `path` of the earliest block it loops back to when that is known right
+    // away, or None after pushing the block to `path` with its successors to walk
+    fn enter(&mut self, start: u16, depth: i64, path: &mut Vec<Frame>, on_path: &mut HashMap<u16, usize>) -> Result<Option<Walked>, u16> {
+        if let Some(i) = on_path.get(&start).copied() {
+            // a loop which leaves the stack deeper each time never ends
+            return if depth > path[i].depth {Err(start)} else {Ok(Some((depth, None, Some(i))))};
+        }
+        if let Some((peak, back)) = self.walked.get(&(start, depth)) {
+            return Ok(Some((*peak, *back, None)));
+        }
+        let block = match self.blocks.get(&start) {
+            Some(b) => *b,
+            None => return Ok(Some((depth, None, None)))
+        };
+
+        let (peak_in, net) = self.effect(block);
+        let running = depth + net;
+        let mut frame = Frame {start, depth, peak: depth + peak_in, back: None, loops_to: None, todo: vec![]};
+        if let Some((callee, back)) = call(block, self.decoder, self.words) {
+            let (callee_peak, callee_back) = self.function(callee)?;
+            frame.peak = frame.peak.max(running + callee_peak);
+            // a callee which never returns ends the path
+            if let Some(d) = callee_back {
+                frame.todo.push((back, running + d));
+            }
+        } else {
+            for s in block.successors.iter().rev() {
+                match s {
+                    Successor::Block(t, _) => frame.todo.push((*t, running)),
+                    Successor::Indirect => frame.back = frame.back.max(Some(running)),
+                    Successor::Exit => ()
+                }
+            }
+        }
+        on_path.insert(start, path.len());
+        path.push(frame);
+        Ok(None)
+    }
+}
+
+// the highest depth, the highest depth on return and the index in the path of
+// the earliest block looped back to of a walk
+type Walked = (i64, Option<i64>, Option<usize>);
+
+// a block being walked
+struct Frame {
+    start: u16,
+    // the depth it was entered with
+    depth: i64,
+    // the highest depth and the highest depth on return so far
+    peak: i64,
+    back: Option<i64>,
+    // the index in the path of the earliest block the walks so far loop back to
+    loops_to: Option<usize>,
+    // successors still to walk, with the depth they start with
+    todo: Vec<(u16, i64)>
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::MCAssembler::Assembler;
+    use crate::SymbolMap::SymbolMap;
+
+    // (entry, part, depth) of every entry point of `asm`, which ends with INT 0
+    fn depths(asm: &str) -> Vec<(u16, char, String)> {
+        let mut assembler = Assembler::new("docs/instructions.toml");
+        assembler.set(None, None, None, String::from("bin"));
+        let (image, map, _) = assembler.assemble(asm);
+        let words = assembler.image_words(&image);
+        let mut decoder = Disassembler::new("docs/instructions.toml");
+        decoder.set_symbols(SymbolMap::parse(&map));
+        let end = decoder.symbols().lines.keys().max().unwrap() + 2;
+
+        let cfg = Cfg::build(&decoder, &words, 0, end);
+        analyze(&cfg, &decoder, &words, 0).into_iter().map(|u| (u.entry, u.part, match u.depth {
+            Depth::Words(d) => format!("{} words", d),
+            Depth::Unbounded(addr) => format!("unbounded at {:04X}", addr)
+        })).collect()
+    }
+
+    #[test]
+    fn calls_add_the_depth_of_the_callee() {
+        let usages = depths("JMP MAIN\nF:\nPUSH %A1\nPUSH %A2\nPOP %A2\nPOP %A1\nRET\nMAIN:\nCALL F\nINT 0\n");
+        // F at 8, the return address of CALL takes one more word
+        assert_eq!(usages, [(0x0000, 'A', String::from("3 words")), (0x0008, 'A', String::from("2 words"))]);
+    }
+
+    #[test]
+    fn loops_which_push_are_unbounded() {
+        let usages = depths("L:\nPUSH %A1\nJMP L\nINT 0\n");
+        assert_eq!(usages, [(0x0000, 'A', String::from("unbounded at 0006"))]);
+        let usages = depths("LOOP:\nPUSH %A1\nPOP %A1\nJMP LOOP\nINT 0\n");
+        assert_eq!(usages, [(0x0000, 'A', String::from("1 words"))]);
+    }
+
+    #[test]
+    fn recursive_calls_are_unbounded() {
+        let usages = depths("JMP MAIN\nF:\nCALL F\nRET\nMAIN:\nCALL F\nINT 0\n");
+        assert_eq!(usages, [(0x0000, 'A', String::from("unbounded at 0008")), (0x0008, 'A', String::from("unbounded at 0008"))]);
+    }
+
+    #[test]
+    fn long_chains_of_blocks_are_walked() {
+        // INC %ASP, then a NOP with a label for every word, which starts a
+        // block, far more than the call stack of a test has room for if each
+        // of them took a frame
+        let mut words = vec![0x0110, 0x8009];
+        words.extend([0x0000; 20000]);
+        words.extend([0x8000, 0]);
+        let mut decoder = Disassembler::new("docs/instructions.toml");
+        decoder.set_symbols(SymbolMap::parse(&(2..20002).map(|a| format!("label L{} {:04X}\n", a, a)).collect::<String>()));
+
+        let cfg = Cfg::build(&decoder, &words, 0, words.len() as u16);
+        let usages = analyze(&cfg, &decoder, &words, 0);
+        assert_eq!(usages.len(), 1);
+        assert!(matches!(usages[0].depth, Depth::Words(1)));
+    }
+}
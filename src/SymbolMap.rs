@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::{fs::File, io::Read};
+
+/// Symbol and line information written by the assembler next to the image,
+/// one "kind name address" entry per line
+pub struct SymbolMap {
+    pub labels: HashMap<u16, String>,
+    pub datas: HashMap<u16, String>,
+    // address -> source line number
+    pub lines: HashMap<u16, usize>,
+    // name -> value of the constants of .EQU, which have no address
+    pub constants: HashMap<String, i64>,
+    pub code_start_address: Option<u16>,
+    pub data_start_address: Option<u16>
+}
+
+impl SymbolMap {
+    pub fn new() -> SymbolMap {
+        SymbolMap {
+            labels: HashMap::new(),
+            datas: HashMap::new(),
+            lines: HashMap::new(),
+            constants: HashMap::new(),
+            code_start_address: None,
+            data_start_address: None
+        }
+    }
+
+    pub fn load(map_file_path: &str) -> SymbolMap {
+        let mut map_file = File::open(map_file_path).unwrap();
+        let mut map = String::new();
+        map_file.read_to_string(&mut map).unwrap();
+
+        SymbolMap::parse(&map)
+    }
+
+    pub fn parse(map: &str) -> SymbolMap {
+        let mut symbols = SymbolMap::new();
+        for (line_num, line) in map.lines().enumerate() {
+            let line = line.split_whitespace().collect::<Vec<_>>();
+            if line.is_empty() {
+                continue;
+            } else if line.len() != 3 {
+                panic!("[ERROR] Map file line{}: Unknown line content", line_num + 1);
+            }
+
+            if line[0] == "equ" {
+                // a sign and the magnitude, the magnitude of i64::MIN is no i64
+                let value = match line[2].strip_prefix("-") {
+                    Some(v) => u64::from_str_radix(v, 16).ok().and_then(|v| 0i64.checked_sub_unsigned(v)),
+                    None => i64::from_str_radix(line[2], 16).ok()
+                };
+                match value {
+                    Some(v) => {symbols.constants.insert(String::from(line[1]), v);},
+                    None => panic!("[ERROR] Map file line{}: Bad constant value {}", line_num + 1, line[2])
+                }
+                continue;
+            }
+            let addr = match u16::from_str_radix(line[2], 16) {
+                Ok(a) => a,
+                Err(_) => panic!("[ERROR] Map file line{}: Bad address {}", line_num + 1, line[2])
+            };
+            match line[0] {
+                "label" => {symbols.labels.insert(addr, String::from(line[1]));},
+                "data" => {symbols.datas.insert(addr, String::from(line[1]));},
+                "line" => match line[1].parse::<usize>() {
+                    Ok(l) => {symbols.lines.insert(addr, l);},
+                    Err(_) => panic!("[ERROR] Map file line{}: Bad line number {}", line_num + 1, line[1])
+                },
+                "segment" => match line[1] {
+                    "CODE" => symbols.code_start_address = Some(addr),
+                    "DATA" => symbols.data_start_address = Some(addr),
+                    _ => ()
+                },
+                _ => panic!("[ERROR] Map file line{}: Unknown symbol kind {}", line_num + 1, line[0])
+            }
+        }
+
+        symbols
+    }
+
+    /// Address of a label or data symbol
+    pub fn address_of(&self, name: &str) -> Option<u16> {
+        self.labels.iter().chain(self.datas.iter()).find(|(_, n)| n.as_str() == name).map(|(a, _)| *a)
+    }
+
+    /// The nearest label at or before `addr`, as "LABEL" or "LABEL+n"
+    pub fn describe(&self, addr: u16) -> Option<String> {
+        let (label_addr, label) = self.labels.iter().filter(|(a, _)| **a <= addr).max_by_key(|(a, _)| **a)?;
+        if *label_addr == addr {
+            Some(label.clone())
+        } else {
+            Some(format!("{}+{}", label, addr - label_addr))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_every_kind() {
+        let symbols = SymbolMap::parse("label LOOP 0006\ndata BUF 1000\nline 7 0006\nsegment CODE 0000\nsegment DATA 1000\n\nequ MIN -8000000000000000\nequ MAX 7FFFFFFFFFFFFFFF\nequ N -1A\n");
+        assert_eq!(symbols.labels.get(&6).map(|l| l.as_str()), Some("LOOP"));
+        assert_eq!(symbols.datas.get(&0x1000).map(|d| d.as_str()), Some("BUF"));
+        assert_eq!(symbols.lines.get(&6), Some(&7));
+        assert_eq!(symbols.code_start_address, Some(0x0000));
+        assert_eq!(symbols.data_start_address, Some(0x1000));
+        assert_eq!(symbols.constants.get("MIN"), Some(&i64::MIN));
+        assert_eq!(symbols.constants.get("MAX"), Some(&i64::MAX));
+        assert_eq!(symbols.constants.get("N"), Some(&-0x1A));
+        assert_eq!(symbols.describe(8), Some(String::from("LOOP+2")));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown line content")]
+    fn parse_rejects_short_lines() {
+        SymbolMap::parse("label LOOP\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad constant value -8000000000000001")]
+    fn parse_rejects_constants_out_of_range() {
+        SymbolMap::parse("equ MIN -8000000000000001\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Bad address 10000")]
+    fn parse_rejects_bad_addresses() {
+        SymbolMap::parse("label LOOP 10000\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown symbol kind proc")]
+    fn parse_rejects_unknown_kinds() {
+        SymbolMap::parse("proc MAIN 0000\n");
+    }
+}
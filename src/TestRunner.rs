@@ -0,0 +1,277 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::MCAssembler::Assembler;
+use crate::Simulator::{Simulator, StopReason};
+use crate::SymbolMap::SymbolMap;
+
+// appended to every test block, the simulator stops on it
+const TEST_END: &str = "INT 0";
+
+struct Expect {
+    line_num: usize,
+    target: String,
+    op: String,
+    value: u16
+}
+
+struct TestBlock {
+    name: String,
+    line_num: usize,
+    // line numbers of the code of this block
+    lines: Vec<usize>,
+    expects: Vec<Expect>
+}
+
+/// Runs `.TEST name` blocks of an assembly file on the simulator
+///
+/// Everything before the first `.TEST` is shared by all blocks and runs
+/// before each of them. A block ends at the next `.TEST`, its `.EXPECT`
+/// lines are checked once the block has run to its end.
+pub struct TestRunner {
+    instructions_file_path: String,
+    max_steps: usize
+}
+
+impl TestRunner {
+    pub fn new(instructions_file_path: &str, max_steps: usize) -> TestRunner {
+        TestRunner {
+            instructions_file_path: String::from(instructions_file_path),
+            max_steps
+        }
+    }
+
+    /// Run all tests in the source and print a report, returns (passed, failed)
+    pub fn run(&self, asm: &str) -> (usize, usize) {
+        // the assembler counts lines after trimming the file
+        let source = asm.trim().lines().collect::<Vec<_>>();
+        let (prelude, blocks) = match self.split(&source) {
+            Ok(r) => r,
+            Err(e) => {
+                print!("{}", e);
+                return (0, 1);
+            }
+        };
+
+        let mut passed = 0;
+        let mut failed = 0;
+        for block in blocks.iter() {
+            match self.run_block(&source, &prelude, block) {
+                Ok(_) => {
+                    println!("[PASS] {} (line {})", block.name, block.line_num);
+                    passed += 1;
+                },
+                Err(e) => {
+                    println!("[FAIL] {} (line {})", block.name, block.line_num);
+                    print!("{}", e);
+                    failed += 1;
+                }
+            }
+        }
+
+        println!("[INFO] {} passed, {} failed", passed, failed);
+        (passed, failed)
+    }
+
+    fn split(&self, source: &[&str]) -> Result<(Vec<usize>, Vec<TestBlock>), String> {
+        let mut error_infos = String::new();
+        let mut prelude = vec![];
+        let mut blocks: Vec<TestBlock> = vec![];
+
+        for (i, line) in source.iter().enumerate() {
+            let line_num = i + 1;
+            let code = line.split(";").collect::<Vec<_>>()[0].trim();
+
+            if code.starts_with(".TEST") {
+                let name = code.trim_start_matches(".TEST").trim();
+                if name.is_empty() {
+                    error_infos += &format!("Line: {} - The test has no name\n", line_num);
+                }
+                blocks.push(TestBlock {
+                    name: String::from(name),
+                    line_num,
+                    lines: vec![],
+                    expects: vec![]
+                });
+            } else if code.starts_with(".EXPECT") {
+                let expect = match parse_expect(code.trim_start_matches(".EXPECT")) {
+                    Ok((target, op, value)) => Expect {line_num, target, op, value},
+                    Err(e) => {
+                        error_infos += &format!("Line: {} - {}\n", line_num, e);
+                        continue;
+                    }
+                };
+                match blocks.last_mut() {
+                    Some(b) => b.expects.push(expect),
+                    None => error_infos += &format!("Line: {} - .EXPECT is outside of a .TEST block\n", line_num)
+                }
+            } else {
+                match blocks.last_mut() {
+                    Some(b) => b.lines.push(line_num),
+                    None => prelude.push(line_num)
+                }
+            }
+        }
+
+        if error_infos.is_empty() {
+            Ok((prelude, blocks))
+        } else {
+            Err(error_infos)
+        }
+    }
+
+    fn run_block(&self, source: &[&str], prelude: &[usize], block: &TestBlock) -> Result<(), String> {
+        // lines of other blocks become comments so that line numbers stay the same
+        let mut asm = source.iter().enumerate().map(|(i, line)|
+                if prelude.contains(&(i + 1)) || block.lines.contains(&(i + 1)) {*line} else {";"}
+            ).collect::<Vec<_>>().join("\n");
+        asm += "\n";
+        asm += TEST_END;
+
+        let mut assembler = Assembler::new(&self.instructions_file_path);
+        assembler.set(None, None, None, String::from("bin"));
+        // the assembler reports errors by panicking, that only fails this test
+        let (image, map, _) = match panic::catch_unwind(AssertUnwindSafe(|| assembler.assemble(&asm))) {
+            Ok(r) => r,
+            Err(e) => {
+                let message = match (e.downcast_ref::<String>(), e.downcast_ref::<&str>()) {
+                    (Some(m), _) => m.as_str(),
+                    (None, Some(m)) => m,
+                    (None, None) => "no message"
+                };
+                return Err(format!("    Assembly failed: {}\n", message));
+            }
+        };
+
+        let mut simulator = Simulator::new(&self.instructions_file_path);
+        // the program starts at its CODE segment
+        simulator.set_symbols(SymbolMap::parse(&map));
+        // the source may choose another image format with .SET ENDIAN or WORD_SIZE
+        simulator.load_words(&assembler.image_words(&image));
+        let reason = simulator.run(self.max_steps);
+        let symbols = simulator.decoder().symbols();
+        match reason {
+            StopReason::Interrupt(0) => (),
+            r => {
+                let line = match symbols.lines.get(&simulator.pc()) {
+                    Some(l) => format!(", line {}: {}", l, source[l - 1].trim()),
+                    None => String::new()
+                };
+                return Err(format!("    {} after {} steps{}\n", r, simulator.steps, line));
+            }
+        }
+
+        let mut error_infos = String::new();
+        for expect in block.expects.iter() {
+            let actual = if expect.target.starts_with("%") {
+                simulator.register(expect.target.trim_start_matches("%"))
+            } else {
+                let addr = expect.target.trim_start_matches("[").trim_end_matches("]");
+                match symbols.address_of(addr).or(parse_number(addr)) {
+                    Some(a) => Some(simulator.memory[a as usize]),
+                    None => None
+                }
+            };
+
+            let pass = match actual {
+                Some(v) if expect.op == "==" => v == expect.value,
+                Some(v) => v != expect.value,
+                None => false
+            };
+            if !pass {
+                let got = match actual {
+                    Some(v) => format!("got {:04X}", v),
+                    None => format!("{} is not defined", expect.target)
+                };
+                error_infos += &format!("    line {}: {} ({})\n", expect.line_num, source[expect.line_num - 1].trim(), got);
+            }
+        }
+
+        if error_infos.is_empty() {
+            Ok(())
+        } else {
+            Err(error_infos)
+        }
+    }
+}
+
+// "%REG == value" or "[ADDR] != value"
+fn parse_expect(expect: &str) -> Result<(String, String, u16), String> {
+    let expect = expect.split_whitespace().collect::<Vec<_>>();
+    if expect.len() != 3 {
+        return Err(String::from("The expectation should look like \".EXPECT %A1 == 5\""));
+    }
+
+    let target = expect[0];
+    if !target.starts_with("%") && (!target.starts_with("[") || !target.ends_with("]")) {
+        return Err(format!("{} is neither a register nor an address", target));
+    }
+    if expect[1] != "==" && expect[1] != "!=" {
+        return Err(format!("Unknown comparison {}", expect[1]));
+    }
+    match parse_number(expect[2]) {
+        Some(v) => Ok((String::from(target), String::from(expect[1]), v)),
+        None => Err(format!("{} cannot be parsed as a valid value", expect[2]))
+    }
+}
+
+// a word, negative numbers in two's complement
+fn parse_number(s: &str) -> Option<u16> {
+    match crate::MCAssembler::parse_number(s) {
+        Some(v) if v >= i16::MIN as i64 && v <= u16::MAX as i64 => Some(v as u16),
+        _ => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runner() -> TestRunner {
+        TestRunner::new("docs/instructions.toml", 1000)
+    }
+
+    #[test]
+    fn parse_expect_takes_registers_and_addresses() {
+        assert_eq!(parse_expect(" %A1 == 5"), Ok((String::from("%A1"), String::from("=="), 5)));
+        assert_eq!(parse_expect("[RESULT]   !=  -1"), Ok((String::from("[RESULT]"), String::from("!="), 0xFFFF)));
+        assert_eq!(parse_expect("[1000H] == 0FFH"), Ok((String::from("[1000H]"), String::from("=="), 0xFF)));
+        assert!(parse_expect("%A1 == ").is_err());
+        assert_eq!(parse_expect("A1 == 5"), Err(String::from("A1 is neither a register nor an address")));
+        assert_eq!(parse_expect("%A1 < 5"), Err(String::from("Unknown comparison <")));
+        assert_eq!(parse_expect("%A1 == 10000H"), Err(String::from("10000H cannot be parsed as a valid value")));
+    }
+
+    #[test]
+    fn split_gives_the_lines_of_each_block() {
+        let source = ["LOAD 1,%A1", ".TEST ONE ; first", "INC %A1", ".EXPECT %A1 == 2", ".TEST TWO", ".EXPECT %A1 == 1"];
+        let (prelude, blocks) = runner().split(&source).unwrap();
+        assert_eq!(prelude, [1]);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!((blocks[0].name.as_str(), blocks[0].line_num, blocks[0].lines.clone()), ("ONE", 2, vec![3]));
+        assert_eq!((blocks[0].expects[0].line_num, blocks[0].expects[0].value), (4, 2));
+        assert_eq!((blocks[1].name.as_str(), blocks[1].lines.len(), blocks[1].expects.len()), ("TWO", 0, 1));
+
+        let source = [".EXPECT %A1 == 1", ".TEST", ".EXPECT %A1 = 1"];
+        assert_eq!(runner().split(&source).err(), Some(String::from(
+            "Line: 1 - .EXPECT is outside of a .TEST block\nLine: 2 - The test has no name\nLine: 3 - Unknown comparison =\n")));
+    }
+
+    #[test]
+    fn blocks_run_from_the_code_segment() {
+        // the data in front of the code is no instruction, a run from 0 stops on it
+        let asm = ".SET CODE 100H\n.SET DATA 0\n.DATA RESULT 0FFFFH\nLOAD 2,%A1\n.TEST PASSES\nINC %A1\nMOV %A1,RESULT\n.EXPECT %A1 == 3\n.EXPECT [RESULT] == 3\n.TEST FAILS\n.EXPECT %A1 != 2\n";
+        assert_eq!(runner().run(asm), (1, 1));
+
+        let source = asm.trim().lines().collect::<Vec<_>>();
+        let (prelude, blocks) = runner().split(&source).unwrap();
+        assert_eq!(runner().run_block(&source, &prelude, &blocks[1]), Err(String::from("    line 11: .EXPECT %A1 != 2 (got 0002)\n")));
+    }
+
+    #[test]
+    fn assembly_errors_are_shown() {
+        let source = [".TEST BAD", "LOAD 1,%Z9"];
+        let (prelude, blocks) = runner().split(&source).unwrap();
+        let error = runner().run_block(&source, &prelude, &blocks[0]).unwrap_err();
+        assert!(error.starts_with("    Assembly failed: [Syntex Error] Line2:"), "{}", error);
+    }
+}
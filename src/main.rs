@@ -1,20 +1,43 @@
+// the modules and the dot instructions are named like the instructions they
+// handle
+#![allow(non_snake_case, non_camel_case_types, clippy::upper_case_acronyms)]
+
 extern crate clap;
 extern crate tokio;
 
-mod SFSpliter;
-mod Instruction;
 mod DotInstruction;
+mod Isa;
+mod MCAssembler;
+mod Disassembler;
+mod Simulator;
+mod SymbolMap;
+mod Debugger;
+mod GdbStub;
+mod TestRunner;
+mod Linter;
+mod Cfg;
+mod StackUsage;
 
-use SFSpliter::SourceFileSpliter;
-use clap::{Parser, command};
+use std::{fs::File, io::Read, path::Path};
+use Disassembler::Disassembler as MCDisassembler;
+use Simulator::Simulator as MCSimulator;
+use Debugger::Debugger as MCDebugger;
+use GdbStub::GdbStub as MCGdbStub;
+use TestRunner::TestRunner as MCTestRunner;
+use Linter::Linter as MCLinter;
+use MCAssembler::check_image_format;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
 #[command(author = "Abonite", version = "0.1.1", about = None, long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    #[arg(short, long)]
-    input_file: String,
-    #[arg(short, long)]
-    output_file: String,
+    #[command(subcommand)]
+    command: Option<Commands>,
+    #[arg(short, long, required = true)]
+    input_file: Option<String>,
+    #[arg(short, long, required = true)]
+    output_file: Option<String>,
     #[arg(long, default_value_t = 0)]
     code_start_addr: u16,
     #[arg(long, default_value_t = 0x1000)]
@@ -23,31 +46,267 @@ struct Args {
     data_start_addr: u16,
     #[arg(long, default_value_t = String::from("bin"))]
     compile_mode: String,
+    /// Extra outputs next to the image, "cfg" writes <output_file>.dot and
+    /// "stack" writes the stack depth of each entry point to <output_file>.stack
+    #[arg(long)]
+    emit: Vec<String>,
+    #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+    instructions_file: String,
+    /// Byte order of the words of the image, "little" or "big" (default big)
+    #[arg(long)]
+    endian: Option<String>,
+    /// Width in bits of the words of the image, 16 or 32 (default 16)
+    #[arg(long)]
+    word_size: Option<u32>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Decode an assembled image back into instructions
+    Disasm {
+        image_file: String,
+        #[arg(short, long)]
+        map_file: Option<String>,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+        /// Byte order of the words of the image, "little" or "big"
+        #[arg(long, default_value_t = String::from("big"))]
+        endian: String,
+        /// Width in bits of the words of the image, 16 or 32
+        #[arg(long, default_value_t = 16)]
+        word_size: u32,
+    },
+    /// Run an assembled image on the instruction-set simulator
+    Run {
+        image_file: String,
+        /// Symbol map written by the assembler, defaults to <image_file>.map,
+        /// the program starts at its CODE segment
+        #[arg(short, long)]
+        map_file: Option<String>,
+        #[arg(long, default_value_t = 1_000_000)]
+        max_steps: usize,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+        /// Byte order of the words of the image, "little" or "big"
+        #[arg(long, default_value_t = String::from("big"))]
+        endian: String,
+        /// Width in bits of the words of the image, 16 or 32
+        #[arg(long, default_value_t = 16)]
+        word_size: u32,
+    },
+    /// Debug an assembled image on the simulator
+    Debug {
+        image_file: String,
+        /// Symbol map written by the assembler, defaults to <image_file>.map
+        #[arg(short, long)]
+        map_file: Option<String>,
+        /// Assembly source, used to show the line of each instruction
+        #[arg(short, long)]
+        source_file: Option<String>,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+        /// Byte order of the words of the image, "little" or "big"
+        #[arg(long, default_value_t = String::from("big"))]
+        endian: String,
+        /// Width in bits of the words of the image, 16 or 32
+        #[arg(long, default_value_t = 16)]
+        word_size: u32,
+    },
+    /// Serve the simulator to GDB over the remote serial protocol
+    Gdb {
+        image_file: String,
+        /// Symbol map written by the assembler, defaults to <image_file>.map,
+        /// the program starts at its CODE segment
+        #[arg(short, long)]
+        map_file: Option<String>,
+        #[arg(short, long, default_value_t = 1234)]
+        port: u16,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+        /// Byte order of the words of the image, "little" or "big"
+        #[arg(long, default_value_t = String::from("big"))]
+        endian: String,
+        /// Width in bits of the words of the image, 16 or 32
+        #[arg(long, default_value_t = 16)]
+        word_size: u32,
+    },
+    /// Assemble and run the .TEST blocks of a source file
+    Test {
+        source_file: String,
+        #[arg(long, default_value_t = 100_000)]
+        max_steps: usize,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+    },
+    /// Check an assembly file for likely mistakes without assembling it
+    Lint {
+        source_file: String,
+        /// Turn a check on, "all" for every check; -W wins over -A, so
+        /// "-A all -W unused-label" runs only that check
+        #[arg(short = 'W')]
+        warn: Vec<String>,
+        /// Turn a check off, "all" for every check; every -A is applied
+        /// before the -W options, whatever their order
+        #[arg(short = 'A')]
+        allow: Vec<String>,
+        #[arg(long, default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+    },
+    /// Work on the instruction set description
+    Isa {
+        #[command(subcommand)]
+        command: IsaCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum IsaCommands {
+    /// Report problems in the instruction set description
+    Check {
+        #[arg(default_value_t = String::from("docs/instructions.toml"))]
+        instructions_file: String,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
 
-    let data = SourceFileSpliter(&args.input_file);
+    match args.command {
+        Some(Commands::Disasm { image_file, map_file, instructions_file, endian, word_size }) => {
+            check_image_format(&endian, word_size);
+            let mut image = vec![];
+            File::open(&image_file).unwrap().read_to_end(&mut image).unwrap();
 
-    let (mut dip, data) = DotInstrctionsProcessor::new(data);
-    if !dip.lexical_check() {
-        panic!("[ERROR] Due to early errors, compiler is stoped");
-    }
-    if !dip.syntax_check() {
-        panic!("[ERROR] Due to early errors, complier is stoped");
-    }
-    if !dip.data_overflow_ckeck() {
-        panic!("[ERROR] Due to early errors, complier is stoped");
+            let mut disassembler = MCDisassembler::new(&instructions_file);
+            if let Some(m) = map_file {
+                disassembler.load_map(&m);
+            }
+            for line in disassembler.disassemble(&image, &endian, word_size) {
+                println!("{}", line);
+            }
+            return;
+        },
+        Some(Commands::Run { image_file, map_file, max_steps, instructions_file, endian, word_size }) => {
+            check_image_format(&endian, word_size);
+            let mut image = vec![];
+            File::open(&image_file).unwrap().read_to_end(&mut image).unwrap();
+
+            let mut simulator = MCSimulator::new(&instructions_file);
+            if let Some(m) = find_map_file(&image_file, map_file) {
+                simulator.load_map(&m);
+            }
+            simulator.load_image(&image, &endian, word_size);
+            let reason = simulator.run(max_steps);
+            println!("[INFO] {} after {} steps", reason, simulator.steps);
+            print!("{}", simulator.dump_registers());
+            return;
+        },
+        Some(Commands::Debug { image_file, map_file, source_file, instructions_file, endian, word_size }) => {
+            check_image_format(&endian, word_size);
+            let mut image = vec![];
+            File::open(&image_file).unwrap().read_to_end(&mut image).unwrap();
+
+            let mut simulator = MCSimulator::new(&instructions_file);
+            match find_map_file(&image_file, map_file) {
+                Some(m) => simulator.load_map(&m),
+                None => println!("[WARNING] No map file, symbols and lines are not available")
+            }
+            simulator.load_image(&image, &endian, word_size);
+            let source = match source_file {
+                Some(s) => {
+                    let mut source = String::new();
+                    File::open(&s).unwrap().read_to_string(&mut source).unwrap();
+                    Some(source)
+                },
+                None => None
+            };
+
+            MCDebugger::new(simulator, source).repl();
+            return;
+        },
+        Some(Commands::Gdb { image_file, map_file, port, instructions_file, endian, word_size }) => {
+            check_image_format(&endian, word_size);
+            let mut image = vec![];
+            File::open(&image_file).unwrap().read_to_end(&mut image).unwrap();
+
+            let mut simulator = MCSimulator::new(&instructions_file);
+            if let Some(m) = find_map_file(&image_file, map_file) {
+                simulator.load_map(&m);
+            }
+            simulator.load_image(&image, &endian, word_size);
+            match MCGdbStub::new(simulator, &endian).listen(port) {
+                Ok(_) => (),
+                Err(e) => panic!("[ERROR] {}", e)
+            }
+            return;
+        },
+        Some(Commands::Test { source_file, max_steps, instructions_file }) => {
+            let mut source = String::new();
+            File::open(&source_file).unwrap().read_to_string(&mut source).unwrap();
+
+            let (_, failed) = MCTestRunner::new(&instructions_file, max_steps).run(&source);
+            if failed != 0 {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Commands::Lint { source_file, warn, allow, instructions_file }) => {
+            let mut source = String::new();
+            File::open(&source_file).unwrap().read_to_string(&mut source).unwrap();
+
+            // -W wins over -A, as their help says
+            let mut linter = MCLinter::new(&instructions_file);
+            for (check, enabled) in allow.iter().map(|c| (c, false)).chain(warn.iter().map(|c| (c, true))) {
+                match linter.set(check, enabled) {
+                    Ok(_) => (),
+                    Err(e) => panic!("[ERROR] {}", e)
+                }
+            }
+            let warnings = linter.lint(&source);
+            for w in warnings.iter() {
+                println!("{}:{}: warning[{}]: {}", source_file, w.line_num, w.check, w.message);
+            }
+            println!("[INFO] {} warning(s)", warnings.len());
+            if !warnings.is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Some(Commands::Isa { command: IsaCommands::Check { instructions_file } }) => {
+            let mut toml = String::new();
+            File::open(&instructions_file).unwrap().read_to_string(&mut toml).unwrap();
+
+            let (errors, warnings) = Isa::Isa::check(&toml);
+            for e in errors.iter() {
+                println!("[ERROR] {}", e);
+            }
+            for w in warnings.iter() {
+                println!("[WARNING] {}", w);
+            }
+            println!("[INFO] {}: {} error(s), {} warning(s)", instructions_file, errors.len(), warnings.len());
+            if !errors.is_empty() {
+                std::process::exit(1);
+            }
+            return;
+        },
+        None => ()
     }
 
-    let (a, b, c, d) = dip.getinfo();
-    println!("[DEBUG]: set info - {:?}", a);
-    println!("[DEBUG]: data info - {:?}", b);
-    println!("[DEBUG]: define info - {:?}", c);
-    println!("[DEBUG]: data buffer - {:?}", d);
+    let mut assembler = MCAssembler::Assembler::new(&args.instructions_file);
+    assembler.set(Some(args.code_start_addr), Some(args.data_start_addr), Some(args.stack_start_addr), args.compile_mode);
+    assembler.set_image_format(args.endian.as_deref().unwrap_or("big"), args.word_size.unwrap_or(16));
+    assembler.set_emit(args.emit);
+    assembler.generate_bcode(&args.input_file.unwrap(), &args.output_file.unwrap());
+}
 
-    let mut a = InstructionProcessor::new(data);
-    a.syntax_check();
+/// The map file given on the command line, or the <image_file>.map the assembler
+/// writes next to the image if there is one
+fn find_map_file(image_file: &str, map_file: Option<String>) -> Option<String> {
+    let default = format!("{}.map", image_file);
+    match map_file {
+        Some(m) => Some(m),
+        None if Path::new(&default).exists() => Some(default),
+        None => None
+    }
 }